@@ -0,0 +1,158 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::constants::HISTORY_SIZE;
+
+/// Lines run from the command line, oldest first, each with the prompt it
+/// was typed after, kept in `~/.snarkyed_history` between sessions.
+pub struct History {
+    entries: Vec<(char, String)>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Reads the saved history, or starts an empty one if there is none.
+    pub fn load() -> History {
+        let path =
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".snarkyed_history"));
+        let entries = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| {
+                text.lines()
+                    .filter_map(|line| {
+                        let mut chars = line.chars();
+                        let prompt = chars.next()?;
+                        Some((prompt, chars.as_str().to_owned()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        History { entries, path }
+    }
+
+    /// Adds `line` as the newest entry, moving it there if it was already
+    /// in, and saves.
+    pub fn add(&mut self, prompt: char, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        self.entries.retain(|(p, l)| *p != prompt || l != line);
+        self.entries.push((prompt, line.to_owned()));
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.drain(..self.entries.len() - HISTORY_SIZE);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut text = String::new();
+        for (prompt, line) in &self.entries {
+            text.push(*prompt);
+            text.push_str(line);
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+
+    /// The nearest entry typed after `prompt` that starts with `prefix`,
+    /// older than the one at `from` or, going `forward`, newer. `from` is
+    /// `None` for the line being typed, past the newest entry.
+    pub fn find(
+        &self,
+        prompt: char,
+        prefix: &str,
+        from: Option<usize>,
+        forward: bool,
+    ) -> Option<(usize, &str)> {
+        let from = from.unwrap_or(self.entries.len());
+        let matches = |&(i, (p, line)): &(usize, &(char, String))| {
+            *p == prompt && line.starts_with(prefix) && i != from
+        };
+        let found = if forward {
+            self.entries.iter().enumerate().skip(from).find(matches)
+        } else {
+            self.entries
+                .iter()
+                .enumerate()
+                .take(from)
+                .rev()
+                .find(matches)
+        };
+        found.map(|(i, (_, line))| (i, line.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history that isn't saved anywhere.
+    fn history(lines: &[(char, &str)]) -> History {
+        let mut history = History {
+            entries: Vec::new(),
+            path: None,
+        };
+        for (prompt, line) in lines {
+            history.add(*prompt, line).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn finds_entries_starting_with_what_is_typed() {
+        let history = history(&[
+            (':', "w"),
+            (':', "set nu"),
+            ('/', "set"),
+            (':', "s/a/b/"),
+            (':', "set ts=4"),
+        ]);
+        assert_eq!(history.find(':', "se", None, false), Some((4, "set ts=4")));
+        assert_eq!(history.find(':', "se", Some(4), false), Some((1, "set nu")));
+        assert_eq!(history.find(':', "se", Some(1), false), None);
+        assert_eq!(
+            history.find(':', "se", Some(1), true),
+            Some((4, "set ts=4"))
+        );
+        assert_eq!(history.find(':', "se", Some(4), true), None);
+        assert_eq!(history.find(':', "", None, true), None);
+        assert_eq!(history.find(':', "", Some(3), false), Some((1, "set nu")));
+        assert_eq!(history.find('/', "", None, false), Some((2, "set")));
+        assert_eq!(history.find('?', "", None, false), None);
+    }
+
+    #[test]
+    fn repeated_lines_move_to_the_newest_place() {
+        let mut history = history(&[(':', "w"), (':', "q"), ('/', "w")]);
+        history.add(':', "w").unwrap();
+        history.add(':', "  ").unwrap();
+        assert_eq!(
+            history.entries,
+            [
+                (':', "q".to_owned()),
+                ('/', "w".to_owned()),
+                (':', "w".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_newest_entries_are_kept() {
+        let mut history = history(&[]);
+        for i in 0..HISTORY_SIZE + 5 {
+            history.add(':', &i.to_string()).unwrap();
+        }
+        assert_eq!(history.entries.len(), HISTORY_SIZE);
+        assert_eq!(history.entries[0].1, "5");
+        assert_eq!(
+            history.find(':', "", None, false).unwrap().1,
+            (HISTORY_SIZE + 4).to_string()
+        );
+    }
+}
@@ -1,11 +1,16 @@
+extern crate unicode_width;
 use glium::glutin::{ElementState, ModifiersState, VirtualKeyCode};
 use glium::{Display, Frame};
-use glium_glyph::glyph_brush::rusttype::Rect;
 use glium_glyph::glyph_brush::{rusttype::Font, GlyphCruncher, Section};
 use glium_glyph::GlyphBrush;
+use unicode_width::UnicodeWidthStr;
 
-use crate::constants::{BASE_FONT_SIZE, CMD_SHIFT_HOLD, NO_MODIFIERS};
-use crate::layout_manager::View;
+mod history;
+
+use history::History;
+
+use crate::constants::{BASE_FONT_SIZE, CTRL_HOLD, NO_MODIFIERS};
+use crate::layout_manager::{Action, View};
 use crate::ui::panel::Panel;
 use crate::ui::color;
 
@@ -13,26 +18,36 @@ pub struct CmdlineView<'a, 'b> {
     glyph_brush: GlyphBrush<'a, 'b>,
     padding: f32,
     font_size: f32,
-    letter_size: Rect<f32>,
     command_text: String,
+    /// The command text other views were last told about.
+    reported: String,
+    /// Byte offset of the cursor in the command text.
+    cursor: usize,
+    prompt: char,
+    history: History,
+    /// The history entry being shown, if any.
+    history_index: Option<usize>,
+    /// What was typed before going through the history, which entries
+    /// have to start with.
+    typed: String,
+    /// What Tab goes through, ending with what was there before.
+    completions: Vec<String>,
+    completion_index: usize,
+    message: Option<String>,
+    /// Whether the message is an error.
+    error: bool,
     visible: bool,
     background: Panel,
+    actions: Vec<Action>,
 }
 
 impl<'a, 'b> CmdlineView<'a, 'b> {
     pub fn new(display: &Display) -> CmdlineView<'a, 'b> {
         let font_regular: &[u8] = include_bytes!("../../assets/haskplex.ttf");
         let fonts = vec![Font::from_bytes(font_regular).unwrap()];
-        let mut gb = GlyphBrush::new(display, fonts);
+        let gb = GlyphBrush::new(display, fonts);
         let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
         let font_size = BASE_FONT_SIZE * hidpi_factor;
-        let letter_size = gb
-            .glyph_bounds(Section {
-                text: "0",
-                scale: glyph_brush::rusttype::Scale::uniform(font_size),
-                ..Section::default()
-            })
-            .unwrap();
 
         let screen_dims = display.get_framebuffer_dimensions();
         let bg_w = 600.0; let bg_h = 30.0;
@@ -42,35 +57,190 @@ impl<'a, 'b> CmdlineView<'a, 'b> {
         CmdlineView {
             glyph_brush: gb,
             padding: 30.0,
-            font_size: font_size,
-            letter_size: letter_size,
+            font_size,
             command_text: "Hello".to_owned(),
+            reported: String::new(),
+            cursor: 0,
+            prompt: ':',
+            history: History::load(),
+            history_index: None,
+            typed: String::new(),
+            completions: Vec::new(),
+            completion_index: 0,
+            message: None,
+            error: false,
             visible: false,
-            background: Panel::new(&display, [bg_x, bg_y], [bg_w, bg_h], color::hex("#4A148C").as_slice()),
+            background: Panel::new(display, [bg_x, bg_y], [bg_w, bg_h], color::hex("#4A148C").as_slice()),
+            actions: Vec::new(),
+        }
+    }
+
+    fn open(&mut self, prompt: char) {
+        self.visible = true;
+        self.prompt = prompt;
+        self.message = None;
+        self.command_text = String::new();
+        self.reported = String::new();
+        self.cursor = 0;
+        self.history_index = None;
+        self.completions.clear();
+    }
+
+    /// Which history the line goes in: searches either way share one.
+    fn history_key(&self) -> char {
+        match self.prompt {
+            '?' => '/',
+            prompt => prompt,
+        }
+    }
+
+    /// Forgets where the history and completions were up to, after the
+    /// text is changed some other way.
+    fn edited(&mut self) {
+        self.history_index = None;
+        self.completions.clear();
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.command_text[..self.cursor].char_indices().next_back().map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.command_text[self.cursor..].chars().next().map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    /// Deletes from `start` up to the cursor.
+    fn delete_back_to(&mut self, start: usize) {
+        self.command_text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+        self.edited();
+    }
+
+    /// Where the word before the cursor starts, skipping spaces. A word is
+    /// a run of letters, digits and `_`, or of other non-blank chars.
+    fn word_start(&self) -> usize {
+        let before = self.command_text[..self.cursor].trim_end();
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        match before.chars().next_back() {
+            Some(last) => before
+                .char_indices()
+                .rev()
+                .find(|&(_, c)| c.is_whitespace() || is_word(c) != is_word(last))
+                .map_or(0, |(i, c)| i + c.len_utf8()),
+            None => 0,
+        }
+    }
+
+    /// Shows the next older history entry, or going `forward` the next
+    /// newer one, that starts with what was typed.
+    fn recall(&mut self, forward: bool) {
+        if self.history_index.is_none() {
+            self.typed = self.command_text.clone();
+        }
+        match self.history.find(self.history_key(), &self.typed, self.history_index, forward) {
+            Some((i, line)) => {
+                self.history_index = Some(i);
+                self.command_text = line.to_owned();
+            }
+            None if forward => {
+                self.history_index = None;
+                self.command_text = self.typed.clone();
+            }
+            None => return,
+        }
+        self.cursor = self.command_text.len();
+        self.completions.clear();
+    }
+
+    /// Asks for completions of the text up to the cursor the first time,
+    /// and goes on to the next one after that.
+    fn complete(&mut self) {
+        if self.completions.is_empty() {
+            self.actions.push(Action::Complete(self.command_text[..self.cursor].to_owned()));
+        } else {
+            self.completion_index = (self.completion_index + 1) % self.completions.len();
+            let completion = self.completions[self.completion_index].clone();
+            self.replace_before_cursor(&completion);
+        }
+    }
+
+    /// Puts in the first completion. When there are more, Tab goes through
+    /// them and then back to what was typed.
+    fn set_completions(&mut self, mut completions: Vec<String>) {
+        if completions.is_empty() {
+            return;
         }
+        let first = completions[0].clone();
+        if completions.len() > 1 {
+            completions.push(self.command_text[..self.cursor].to_owned());
+            self.completions = completions;
+            self.completion_index = 0;
+        }
+        self.replace_before_cursor(&first);
+    }
+
+    fn replace_before_cursor(&mut self, text: &str) {
+        self.command_text.replace_range(..self.cursor, text);
+        self.cursor = text.len();
+        self.history_index = None;
     }
 }
 
 impl<'a, 'b> View for CmdlineView<'a, 'b> {
     fn update(&mut self, display: &Display) {
+        if self.visible && self.command_text != self.reported {
+            self.reported = self.command_text.clone();
+            self.actions.push(Action::CmdlineChanged(self.reported.clone()));
+        }
         let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
         self.font_size = BASE_FONT_SIZE * hidpi_factor;
         let screen_dims = display.get_framebuffer_dimensions();
         let text_x = screen_dims.0 as f32 / hidpi_factor / 2.0 - (300.0 / hidpi_factor) + self.padding * hidpi_factor;
         let text_y = screen_dims.1 as f32 / 2.0 - self.font_size / 2.0;
 
+        let letter_width = self
+            .glyph_brush
+            .glyph_bounds(Section {
+                text: "0",
+                scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+                ..Section::default()
+            })
+            .map_or(0.0, |rect| rect.width());
+
+        let (text, text_color) = match (&self.message, self.visible) {
+            (Some(message), false) if self.error => (message.clone(), color::hex("#FF5F56")),
+            (Some(message), false) => (message.clone(), color::hex("#FFFFFF")),
+            _ => {
+                let mut text = self.prompt.to_string();
+                text.push_str(&self.command_text);
+                (text, color::hex("#FF5F56"))
+            }
+        };
+
         self.glyph_brush.queue(Section {
-            text: &self.command_text,
+            text: &text,
             bounds: (screen_dims.0 as f32 - self.padding, screen_dims.1 as f32),
             screen_position: (text_x, text_y),
             scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
-            color: color::hex("#FF5F56").as_slice(),
+            color: text_color.as_slice(),
             ..Section::default()
         });
+
+        if self.visible {
+            let x = 1 + self.command_text[..self.cursor].width();
+            let width = self.command_text[self.cursor..].chars().next().map_or(1, |c| c.to_string().width().max(1));
+            self.glyph_brush.queue(Section {
+                text: &"█".repeat(width),
+                screen_position: (text_x + letter_width * x as f32, text_y),
+                scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+                color: color::hexa("#3A60D7", 0.4).as_slice(),
+                ..Section::default()
+            });
+        }
     }
 
     fn draw(&mut self, display: &Display, target: &mut Frame) {
-        if self.visible {
+        if self.visible || self.message.is_some() {
             self.background.draw(target);
             self.glyph_brush.draw_queued(display, target);
         }
@@ -82,27 +252,83 @@ impl<'a, 'b> View for CmdlineView<'a, 'b> {
         state: ElementState,
         modifiers: ModifiersState,
     ) {
+        if state == ElementState::Pressed {
+            self.message = None;
+        }
+        if self.visible && state == ElementState::Pressed && key_code != VirtualKeyCode::Tab {
+            self.completions.clear();
+        }
         match (key_code, state, modifiers) {
-            (VirtualKeyCode::P, ElementState::Pressed, CMD_SHIFT_HOLD) => {
-                self.visible = true;
-                self.command_text = String::new();
-            }
             (VirtualKeyCode::Escape, ElementState::Pressed, NO_MODIFIERS) => {
+                if self.visible {
+                    self.actions.push(Action::CmdlineCancelled);
+                }
                 self.visible = false;
             }
+            (VirtualKeyCode::Return, ElementState::Pressed, NO_MODIFIERS) if self.visible => {
+                self.visible = false;
+                if let Err(err) = self.history.add(self.history_key(), &self.command_text) {
+                    self.actions.push(Action::ShowError(format!("Can't save the command history: {}", err)));
+                }
+                self.actions
+                    .push(Action::RunCommand(self.command_text.clone()));
+            }
+            _ if !self.visible || state != ElementState::Pressed => (),
+            (VirtualKeyCode::Left, _, NO_MODIFIERS) => self.cursor = self.prev_boundary(),
+            (VirtualKeyCode::Right, _, NO_MODIFIERS) => self.cursor = self.next_boundary(),
+            (VirtualKeyCode::Home, _, NO_MODIFIERS) | (VirtualKeyCode::B, _, CTRL_HOLD) => self.cursor = 0,
+            (VirtualKeyCode::End, _, NO_MODIFIERS) | (VirtualKeyCode::E, _, CTRL_HOLD) => {
+                self.cursor = self.command_text.len();
+            }
+            (VirtualKeyCode::Up, _, NO_MODIFIERS) => self.recall(false),
+            (VirtualKeyCode::Down, _, NO_MODIFIERS) => self.recall(true),
+            (VirtualKeyCode::Delete, _, NO_MODIFIERS) => {
+                let end = self.next_boundary();
+                self.command_text.replace_range(self.cursor..end, "");
+                self.edited();
+            }
+            (VirtualKeyCode::W, _, CTRL_HOLD) => self.delete_back_to(self.word_start()),
+            (VirtualKeyCode::U, _, CTRL_HOLD) => self.delete_back_to(0),
+            (VirtualKeyCode::Tab, _, NO_MODIFIERS) if self.prompt == ':' => self.complete(),
             _ => (),
         }
     }
 
     fn push_char(&mut self, c: char) {
-        if self.visible {
-            self.command_text.push(c);
+        if self.visible && !c.is_control() {
+            self.command_text.insert(self.cursor, c);
+            self.cursor += c.len_utf8();
+            self.edited();
         }
     }
 
     fn pop_char(&mut self) {
         if self.visible {
-            self.command_text.pop();
+            self.delete_back_to(self.prev_boundary());
+        }
+    }
+
+    fn captures_input(&self) -> bool {
+        self.visible
+    }
+
+    fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    fn dispatch(&mut self, action: &Action) {
+        match action {
+            Action::OpenCmdline(prompt) => self.open(*prompt),
+            Action::ShowMessage(message) => {
+                self.message = Some(message.clone());
+                self.error = false;
+            }
+            Action::ShowError(message) => {
+                self.message = Some(message.clone());
+                self.error = true;
+            }
+            Action::Completions(completions) => self.set_completions(completions.clone()),
+            _ => (),
         }
     }
 }
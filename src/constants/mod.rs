@@ -2,6 +2,38 @@ use glium::glutin::ModifiersState;
 
 pub const BASE_FONT_SIZE: f32 = 18.0;
 
+/// Height of the status line along the bottom of the window, in logical
+/// pixels.
+pub const STATUS_LINE_HEIGHT: f32 = 28.0;
+
+/// Default number of screen columns between tab stops.
+pub const TAB_STOP: usize = 4;
+
+/// Default number of screen columns `>` and `<` shift lines by.
+pub const SHIFT_WIDTH: usize = 4;
+
+/// Whether buffers indent with spaces rather than tabs by default.
+pub const EXPAND_TAB: bool = true;
+
+/// Default number of lines kept in sight above and below the cursor.
+pub const SCROLL_OFF: usize = 3;
+
+/// Default number of columns kept in sight left and right of the cursor
+/// when lines don't wrap.
+pub const SIDE_SCROLL_OFF: usize = 5;
+
+/// Whether long lines wrap onto more rows by default.
+pub const WRAP: bool = true;
+
+/// Whether the gutter shows line numbers by default.
+pub const NUMBER: bool = true;
+
+/// Whether those line numbers count from the cursor line by default.
+pub const RELATIVE_NUMBER: bool = false;
+
+/// Number of lines the command line remembers between sessions.
+pub const HISTORY_SIZE: usize = 100;
+
 pub const NO_MODIFIERS: ModifiersState = ModifiersState {
     alt: false,
     ctrl: false,
@@ -16,11 +48,11 @@ pub const CTRL_HOLD: ModifiersState = ModifiersState {
     shift: false,
 };
 
-pub const SHIFT_HOLD: ModifiersState = ModifiersState {
+pub const CMD_HOLD: ModifiersState = ModifiersState {
     alt: false,
     ctrl: false,
-    logo: false,
-    shift: true,
+    logo: true,
+    shift: false,
 };
 
 pub const CMD_SHIFT_HOLD: ModifiersState = ModifiersState {
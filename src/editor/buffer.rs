@@ -1,14 +1,160 @@
 extern crate ropey;
 use ropey::Rope;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::error::BufferError;
+use super::history::{Edit, History};
+use super::options::Options;
+use super::range::{Change, Position, Range};
 
 pub struct Buffer {
     content: Rope,
+    path: Option<PathBuf>,
+    history: History,
+    saved_revision: usize,
+    read_only: bool,
+    /// Whether lines end in `\r\n`, as the first one did when the buffer
+    /// was loaded. Line breaks put in are made to match.
+    crlf: bool,
+    /// The changes made since the last `take_changes`, so positions kept
+    /// outside the buffer can follow them.
+    changes: Vec<Change>,
+    options: Options,
 }
 
 impl Buffer {
-    pub fn new(file: &str) -> Buffer {
-        let content = Rope::from_reader(std::fs::File::open(file).unwrap()).unwrap();
-        Buffer { content: content }
+    /// Loads `file`, or starts an empty buffer for it if it doesn't exist
+    /// yet. Files that aren't valid UTF-8 are decoded lossily and opened
+    /// read-only, so saving can't clobber the bytes that were replaced.
+    pub fn open(file: &str) -> Result<Buffer, BufferError> {
+        let bytes = match fs::read(file) {
+            Ok(bytes) => bytes,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(BufferError::Io(PathBuf::from(file), err)),
+        };
+        Ok(Buffer {
+            path: Some(PathBuf::from(file)),
+            ..Buffer::decode(bytes)
+        })
+    }
+
+    /// An unnamed buffer filled from `reader`, e.g. stdin.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Buffer, BufferError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|err| BufferError::Io(PathBuf::from("-"), err))?;
+        Ok(Buffer::decode(bytes))
+    }
+
+    fn decode(bytes: Vec<u8>) -> Buffer {
+        match String::from_utf8(bytes) {
+            Ok(text) => Buffer::with_content(Rope::from_str(&text)),
+            Err(err) => Buffer {
+                read_only: true,
+                ..Buffer::with_content(Rope::from_str(&String::from_utf8_lossy(err.as_bytes())))
+            },
+        }
+    }
+
+    pub fn empty() -> Buffer {
+        Buffer::with_content(Rope::new())
+    }
+
+    fn with_content(content: Rope) -> Buffer {
+        let first = content.line(0);
+        let len = first.len_chars();
+        let crlf = len >= 2 && first.char(len - 2) == '\r' && first.char(len - 1) == '\n';
+        Buffer {
+            content,
+            path: None,
+            history: History::new(),
+            saved_revision: 0,
+            read_only: false,
+            crlf,
+            changes: Vec::new(),
+            options: Options::new(),
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether the buffer's file is the one at `path`, however either was
+    /// spelt, so `./x` and `x` are the same file.
+    pub fn has_path(&self, path: &Path) -> bool {
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.path()
+            .is_some_and(|own| canonical(own) == canonical(path))
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut Options {
+        &mut self.options
+    }
+
+    /// Whether the buffer changed since it was last loaded or saved.
+    pub fn is_dirty(&self) -> bool {
+        self.history.current() != self.saved_revision
+    }
+
+    /// Writes the buffer back to the file it was loaded from.
+    pub fn save(&mut self) -> Result<usize, BufferError> {
+        match self.path.clone() {
+            Some(path) => self.save_as(&path),
+            None => Err(BufferError::NoFileName),
+        }
+    }
+
+    /// Writes the buffer to `path` and makes it the buffer's file. The
+    /// content goes to a temporary file next to the target first, which is
+    /// then renamed over it, so a failed write never truncates the original.
+    /// Returns the number of bytes written.
+    pub fn save_as(&mut self, path: &Path) -> Result<usize, BufferError> {
+        if self.read_only {
+            return Err(BufferError::ReadOnly);
+        }
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let file_name = target.file_name().ok_or(BufferError::NoFileName)?;
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(".{}.tmp", std::process::id()));
+        let tmp_path = target.with_file_name(tmp_name);
+
+        let result = self.write_to(&tmp_path).and_then(|_| {
+            if let Ok(metadata) = fs::metadata(&target) {
+                fs::set_permissions(&tmp_path, metadata.permissions())?;
+            }
+            fs::rename(&tmp_path, &target)
+        });
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(BufferError::Io(path.to_path_buf(), err));
+        }
+
+        self.path = Some(path.to_path_buf());
+        self.saved_revision = self.history.current();
+        self.history.split_group();
+        Ok(self.content.len_bytes())
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let file = fs::File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        self.content.write_to(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()
     }
 
     pub fn get_line_at(&self, line: usize) -> String {
@@ -19,14 +165,434 @@ impl Buffer {
         self.content.len_lines()
     }
 
-    pub fn get_lines(&self, from: usize, to: usize) -> String {
-        let max_len = self.content.len_lines();
-        let start = self.content.line_to_char(from);
-        let end = if max_len > to {
-            self.content.line_to_char(to)
+    /// Whether lines end in `\r\n` rather than `\n`, going by the first
+    /// one.
+    pub fn uses_crlf(&self) -> bool {
+        self.crlf
+    }
+
+    /// Number of chars on `line`, not counting the line break.
+    pub fn line_len(&self, line: usize) -> usize {
+        let slice = self.content.line(line);
+        let mut len = slice.len_chars();
+        if len > 0 && slice.char(len - 1) == '\n' {
+            len -= 1;
+        }
+        if len > 0 && slice.char(len - 1) == '\r' {
+            len -= 1;
+        }
+        len
+    }
+
+    /// The text of `line` without its line break.
+    pub fn line_text(&self, line: usize) -> String {
+        let slice = self.content.line(line);
+        String::from(slice.slice(..self.line_len(line)))
+    }
+
+    /// The text of `line` without its line break, borrowed from the rope
+    /// unless it spans more than one of its chunks.
+    pub fn line_str(&self, line: usize) -> Cow<'_, str> {
+        let slice = self.content.line(line);
+        slice.slice(..self.line_len(line)).into()
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.content.len_chars()
+    }
+
+    pub fn char_at(&self, idx: usize) -> char {
+        self.content.char(idx)
+    }
+
+    /// Clamps `pos` so it points at an existing line and at most one past
+    /// the last char of that line.
+    pub fn clamp(&self, pos: Position) -> Position {
+        let line = pos.line.min(self.content.len_lines() - 1);
+        Position::new(line, pos.col.min(self.line_len(line)))
+    }
+
+    pub fn pos_to_char(&self, pos: Position) -> usize {
+        let pos = self.clamp(pos);
+        self.content.line_to_char(pos.line) + pos.col
+    }
+
+    pub fn char_to_pos(&self, idx: usize) -> Position {
+        let idx = idx.min(self.content.len_chars());
+        let line = self.content.char_to_line(idx);
+        Position::new(line, idx - self.content.line_to_char(line))
+    }
+
+    pub fn slice(&self, range: Range) -> String {
+        let start = self.pos_to_char(range.start);
+        let end = self.pos_to_char(range.end);
+        String::from(self.content.slice(start..end))
+    }
+
+    pub fn insert(&mut self, pos: Position, text: &str) -> Change {
+        self.replace(Range::new(pos, pos), text)
+    }
+
+    pub fn delete(&mut self, range: Range) -> Change {
+        self.replace(range, "")
+    }
+
+    /// Replaces the text in `range` with `text`, whose line breaks are
+    /// made the buffer's own. A read-only buffer is left as it is, whatever
+    /// the caller checked, and the change says nothing was replaced.
+    pub fn replace(&mut self, range: Range, text: &str) -> Change {
+        let text = &*self.with_line_ending(text);
+        let start = self.clamp(range.start);
+        let old_end = self.clamp(range.end);
+        if self.read_only {
+            return Change {
+                start,
+                old_end,
+                new_end: old_end,
+            };
+        }
+        let from = self.pos_to_char(start);
+        let to = self.pos_to_char(old_end);
+        let removed = String::from(self.content.slice(from..to));
+        self.content.remove(from..to);
+        self.content.insert(from, text);
+        if removed.is_empty() && text.is_empty() {
+            return Change {
+                start,
+                old_end,
+                new_end: start,
+            };
+        }
+        self.history.record(
+            Edit {
+                at: from,
+                removed,
+                inserted: text.to_owned(),
+            },
+            start,
+        );
+        let change = Change {
+            start,
+            old_end,
+            new_end: self.char_to_pos(from + text.chars().count()),
+        };
+        self.changes.push(change);
+        change
+    }
+
+    /// `text` with its line breaks, `\n` or `\r\n`, all made the ones the
+    /// buffer uses.
+    fn with_line_ending<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        if !text.contains('\n') {
+            return Cow::Borrowed(text);
+        }
+        let text = text.replace("\r\n", "\n");
+        if self.crlf {
+            Cow::Owned(text.replace('\n', "\r\n"))
         } else {
-            self.content.line_to_char(max_len)
+            Cow::Owned(text)
+        }
+    }
+
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Breaks the line at `pos`, moving the rest of it onto a new line.
+    pub fn split_line(&mut self, pos: Position) -> Change {
+        self.insert(pos, "\n")
+    }
+
+    /// Joins `line` with the one below it, putting `separator` in place of
+    /// the line break.
+    pub fn join_lines(&mut self, line: usize, separator: &str) -> Option<Change> {
+        if line + 1 >= self.content.len_lines() {
+            return None;
+        }
+        let eol = Position::new(line, self.line_len(line));
+        Some(self.replace(Range::new(eol, Position::new(line + 1, 0)), separator))
+    }
+
+    /// Joins `line` with the one below it like `J`: the indent of the line
+    /// below goes, and a space separates the two unless either is blank.
+    /// The two edits this takes go into the caller's undo group, if any.
+    pub fn join_trimmed(&mut self, line: usize) -> Option<Change> {
+        if line + 1 >= self.content.len_lines() {
+            return None;
+        }
+        let next = self.line_text(line + 1);
+        let indent = next.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        let separator = if self.line_len(line) == 0 || next.trim().is_empty() {
+            ""
+        } else {
+            " "
         };
-        String::from(self.content.slice(start..end))
+        self.delete(Range::new(
+            Position::new(line + 1, 0),
+            Position::new(line + 1, indent),
+        ));
+        self.join_lines(line, separator)
+    }
+
+    /// Makes the edits until the matching `end_undo_group` undo as a single
+    /// step, which puts the cursor back at `cursor`.
+    pub fn begin_undo_group(&mut self, cursor: Position) {
+        self.history.begin_group(cursor);
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Keeps the current undo group open through `end_undo_group` calls
+    /// until it is released.
+    pub fn hold_undo_group(&mut self, held: bool) {
+        self.history.hold_group(held);
+    }
+
+    /// Reverts the last change, returning where to put the cursor.
+    pub fn undo(&mut self) -> Option<Position> {
+        let revision = self.history.undo()?;
+        self.revert(revision);
+        Some(self.clamp(self.history.cursor(revision)))
+    }
+
+    /// Reapplies the last undone change, returning where to put the cursor.
+    pub fn redo(&mut self) -> Option<Position> {
+        let revision = self.history.redo()?;
+        self.apply(revision);
+        Some(self.clamp(self.history.cursor(revision)))
+    }
+
+    /// Moves `steps` states back (negative) or forward in time, following
+    /// the order changes were made in rather than the undo branch, so states
+    /// on abandoned branches can be reached too.
+    pub fn travel(&mut self, steps: isize) -> Option<Position> {
+        let current = self.history.current() as isize;
+        let last = self.history.revision_count() as isize - 1;
+        let target = (current + steps).max(0).min(last);
+        if target == current {
+            return None;
+        }
+        let (reverts, applies) = self.history.travel(target as usize);
+        for revision in reverts.iter() {
+            self.revert(*revision);
+        }
+        for revision in applies.iter() {
+            self.apply(*revision);
+        }
+        let changed = if steps < 0 {
+            *reverts.last().unwrap_or(&(target as usize))
+        } else {
+            target as usize
+        };
+        Some(self.clamp(self.history.cursor(changed)))
+    }
+
+    fn revert(&mut self, revision: usize) {
+        for edit in self.history.edits(revision).iter().rev() {
+            let end = edit.at + edit.inserted.chars().count();
+            self.content.remove(edit.at..end);
+            self.content.insert(edit.at, &edit.removed);
+        }
+    }
+
+    fn apply(&mut self, revision: usize) {
+        for edit in self.history.edits(revision).iter() {
+            let end = edit.at + edit.removed.chars().count();
+            self.content.remove(edit.at..end);
+            self.content.insert(edit.at, &edit.inserted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from(text: &str) -> Buffer {
+        Buffer::from_reader(text.as_bytes()).unwrap()
+    }
+
+    fn pos(line: usize, col: usize) -> Position {
+        Position::new(line, col)
+    }
+
+    fn change(start: Position, old_end: Position, new_end: Position) -> Change {
+        Change {
+            start,
+            old_end,
+            new_end,
+        }
+    }
+
+    #[test]
+    fn insert_reports_the_inserted_span() {
+        let mut buffer = from("ab\ncd");
+        assert_eq!(
+            buffer.insert(pos(0, 1), "X"),
+            change(pos(0, 1), pos(0, 1), pos(0, 2))
+        );
+        assert_eq!(
+            buffer.insert(pos(1, 2), "\nef"),
+            change(pos(1, 2), pos(1, 2), pos(2, 2))
+        );
+        assert_eq!(buffer.content.to_string(), "aXb\ncd\nef");
+    }
+
+    #[test]
+    fn delete_across_lines() {
+        let mut buffer = from("ab\ncd");
+        assert_eq!(
+            buffer.delete(Range::new(pos(0, 1), pos(1, 1))),
+            change(pos(0, 1), pos(1, 1), pos(0, 1))
+        );
+        assert_eq!(buffer.content.to_string(), "ad");
+    }
+
+    #[test]
+    fn replace_with_more_lines() {
+        let mut buffer = from("ab\ncd");
+        assert_eq!(
+            buffer.replace(Range::new(pos(0, 0), pos(0, 2)), "x\ny"),
+            change(pos(0, 0), pos(0, 2), pos(1, 1))
+        );
+        assert_eq!(buffer.content.to_string(), "x\ny\ncd");
+        assert_eq!(buffer.take_changes().len(), 1);
+    }
+
+    #[test]
+    fn split_and_join_lines() {
+        let mut buffer = from("ab\ncd");
+        assert_eq!(
+            buffer.split_line(pos(0, 1)),
+            change(pos(0, 1), pos(0, 1), pos(1, 0))
+        );
+        assert_eq!(buffer.content.to_string(), "a\nb\ncd");
+        assert_eq!(
+            buffer.join_lines(1, " "),
+            Some(change(pos(1, 1), pos(2, 0), pos(1, 2)))
+        );
+        assert_eq!(buffer.content.to_string(), "a\nb cd");
+        assert_eq!(buffer.join_lines(1, " "), None);
+    }
+
+    #[test]
+    fn joining_trims_the_indent_and_undoes_with_its_group() {
+        let mut buffer = from("a\nb\n    c\nd");
+        // What `3J` does.
+        buffer.begin_undo_group(pos(0, 0));
+        assert_eq!(
+            buffer.join_trimmed(0),
+            Some(change(pos(0, 1), pos(1, 0), pos(0, 2)))
+        );
+        assert_eq!(
+            buffer.join_trimmed(0),
+            Some(change(pos(0, 3), pos(1, 0), pos(0, 4)))
+        );
+        buffer.end_undo_group();
+        assert_eq!(buffer.content.to_string(), "a b c\nd");
+        assert_eq!(buffer.undo(), Some(pos(0, 0)));
+        assert_eq!(buffer.content.to_string(), "a\nb\n    c\nd");
+        assert_eq!(buffer.undo(), None);
+
+        let mut buffer = from("a\n\nb");
+        buffer.join_trimmed(0);
+        assert_eq!(buffer.content.to_string(), "a\nb");
+        assert_eq!(buffer.join_trimmed(1), None);
+    }
+
+    #[test]
+    fn positions_at_the_end_of_the_buffer() {
+        let buffer = from("ab\ncd");
+        assert_eq!(buffer.pos_to_char(pos(1, 2)), 5);
+        assert_eq!(buffer.clamp(pos(5, 9)), pos(1, 2));
+        assert_eq!(buffer.pos_to_char(pos(5, 9)), 5);
+        assert_eq!(buffer.char_to_pos(5), pos(1, 2));
+        assert_eq!(buffer.char_to_pos(99), pos(1, 2));
+
+        let buffer = from("ab\n");
+        assert_eq!(buffer.get_lines_count(), 2);
+        assert_eq!(buffer.clamp(pos(9, 9)), pos(1, 0));
+        assert_eq!(buffer.char_to_pos(3), pos(1, 0));
+    }
+
+    #[test]
+    fn crlf_line_breaks_are_not_part_of_lines() {
+        let buffer = from("ab\r\ncd\r\n");
+        assert!(buffer.uses_crlf());
+        assert_eq!(buffer.line_len(0), 2);
+        assert_eq!(buffer.line_len(1), 2);
+        assert_eq!(buffer.line_text(0), "ab");
+        assert_eq!(buffer.line_str(1), "cd");
+        assert_eq!(buffer.clamp(pos(0, 9)), pos(0, 2));
+        assert_eq!(buffer.pos_to_char(pos(1, 0)), 4);
+        assert_eq!(buffer.pos_to_char(pos(1, 9)), 6);
+        assert_eq!(buffer.char_to_pos(4), pos(1, 0));
+        assert!(!from("ab\ncd").uses_crlf());
+    }
+
+    #[test]
+    fn line_breaks_put_in_match_the_buffer() {
+        let mut buffer = from("ab\r\ncd\r\n");
+        assert_eq!(
+            buffer.split_line(pos(0, 1)),
+            change(pos(0, 1), pos(0, 1), pos(1, 0))
+        );
+        assert_eq!(buffer.content.to_string(), "a\r\nb\r\ncd\r\n");
+        buffer.insert(pos(3, 0), "x\ny\r\n");
+        assert_eq!(buffer.content.to_string(), "a\r\nb\r\ncd\r\nx\r\ny\r\n");
+
+        let mut buffer = from("ab\n");
+        buffer.insert(pos(1, 0), "x\r\ny");
+        assert_eq!(buffer.content.to_string(), "ab\nx\ny");
+    }
+
+    #[test]
+    fn read_only_buffers_refuse_edits() {
+        let mut buffer = Buffer::decode(b"ab\xFF\ncd".to_vec());
+        assert!(buffer.is_read_only());
+        let before = buffer.content.to_string();
+        assert_eq!(
+            buffer.insert(pos(0, 1), "x"),
+            change(pos(0, 1), pos(0, 1), pos(0, 1))
+        );
+        buffer.delete(Range::new(pos(0, 0), pos(1, 1)));
+        buffer.split_line(pos(0, 1));
+        buffer.join_lines(0, " ");
+        assert_eq!(buffer.content.to_string(), before);
+        assert!(!buffer.is_dirty());
+        assert!(buffer.take_changes().is_empty());
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn edits_after_saving_inside_an_undo_group_make_the_buffer_dirty() {
+        let path = std::env::temp_dir().join(format!("snarkyed-dirty-{}", std::process::id()));
+        let mut buffer = Buffer::empty();
+        buffer.begin_undo_group(Position::new(0, 0));
+        buffer.insert(Position::new(0, 0), "a");
+        buffer.save_as(&path).unwrap();
+        assert!(!buffer.is_dirty());
+        buffer.insert(Position::new(0, 1), "b");
+        assert!(buffer.is_dirty());
+        buffer.end_undo_group();
+        assert!(buffer.is_dirty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn paths_are_compared_as_the_files_they_name() {
+        let dir = std::env::temp_dir();
+        let name = format!("snarkyed-path-{}", std::process::id());
+        let mut buffer = Buffer::empty();
+        assert!(!buffer.has_path(&dir.join(&name)));
+        buffer.save_as(&dir.join(&name)).unwrap();
+        assert!(buffer.has_path(&dir.join(&name)));
+        assert!(buffer.has_path(&dir.join(".").join(&name)));
+        assert!(!buffer.has_path(&dir.join("other")));
+        fs::remove_file(dir.join(&name)).unwrap();
+        // Once gone, the paths are compared as written.
+        assert!(buffer.has_path(&dir.join(&name)));
     }
 }
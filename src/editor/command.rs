@@ -0,0 +1,79 @@
+use super::mode::VisualKind;
+use super::motion::Motion;
+use super::search::Direction;
+use super::text_object::TextObject;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+}
+
+/// The text an operator acts on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    Motion(Motion),
+    Object(TextObject),
+}
+
+/// A normal mode command, resolved from a sequence of keys.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Command {
+    Move(Motion),
+    /// An operator applied to the text the motion moves over.
+    Operate(Operator, Target),
+    /// An operator doubled up, like `dd`, acting on whole lines.
+    OperateLines(Operator),
+    InsertBefore,
+    InsertAtBol,
+    Append,
+    AppendAtEol,
+    OpenBelow,
+    OpenAbove,
+    DeleteChar,
+    JoinLines,
+    /// `P` when true, `p` otherwise.
+    Paste(bool),
+    Undo,
+    Redo,
+    Earlier,
+    Later,
+    ScrollDown,
+    ScrollUp,
+    ScrollLeft,
+    ScrollRight,
+    /// `zs`, scrolling sideways to put the cursor at the left edge.
+    ScrollCursorToStart,
+    /// `ze`, scrolling sideways to put the cursor at the right edge.
+    ScrollCursorToEnd,
+    Save,
+    EnterCmdline,
+    /// `/` or `?`, typing a search into the command line.
+    Search(Direction),
+    /// `N` when true, `n` otherwise.
+    SearchNext(bool),
+    /// `*` or `#`, searching for the word under the cursor.
+    SearchWord(Direction),
+    /// Starts a visual mode, or switches to another one, or leaves it when
+    /// it is the current one.
+    Visual(VisualKind),
+    LeaveVisual,
+    /// `o` in visual mode: moves the cursor to the other end of the
+    /// selection.
+    SwapAnchor,
+    /// Selects a text object in visual mode.
+    SelectObject(TextObject),
+    /// An operator applied to the visual selection.
+    OperateSelection(Operator),
+    /// Block mode `A` when true, `I` otherwise.
+    BlockInsert(bool),
+    /// Adds a cursor on the next match of the word under the cursor.
+    AddCursorAtNextMatch,
+    AddCursorBelow,
+    AddCursorAbove,
+    /// Drops every cursor but the main one.
+    SingleCursor,
+}
@@ -1,10 +1,22 @@
+use super::range::Position;
+
+/// A cursor, in buffer coordinates.
+#[derive(Copy, Clone, Debug)]
 pub struct Cursor {
-    pub row: i32,
-    pub col: i32,
+    pub pos: Position,
+    /// Where its visual selection starts.
+    pub anchor: Position,
+    /// The column `j` and `k` try to get back to after passing lines too
+    /// short for it.
+    pub goal_col: Option<usize>,
 }
 
 impl Cursor {
-    pub fn new() -> Cursor {
-        Cursor { row: 0, col: 0 }
+    pub fn new(pos: Position) -> Cursor {
+        Cursor {
+            pos,
+            anchor: pos,
+            goal_col: None,
+        }
     }
 }
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum BufferError {
+    /// Reading or writing the file failed.
+    Io(PathBuf, io::Error),
+    /// The buffer was opened read-only and can't be changed or written.
+    ReadOnly,
+    /// The buffer has no file to be written to.
+    NoFileName,
+}
+
+impl fmt::Display for BufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BufferError::Io(path, err) => write!(f, "\"{}\": {}", path.display(), err),
+            BufferError::ReadOnly => write!(f, "Buffer is read-only"),
+            BufferError::NoFileName => write!(f, "No file name"),
+        }
+    }
+}
+
+impl std::error::Error for BufferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BufferError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Why a command typed into the command line couldn't be run.
+#[derive(Debug)]
+pub enum ExError {
+    NotACommand(String),
+    /// A line past the end of the buffer, or an address that doesn't parse.
+    InvalidRange,
+    /// A range given to a command that doesn't act on lines.
+    NoRangeAllowed,
+    /// Something after a command that doesn't take it.
+    TrailingCharacters(String),
+    /// Changes to the named buffer would be lost.
+    NoWriteSinceLastChange(String),
+    NoFileName,
+    UnknownOption(String),
+    /// An option or command given a value it can't take.
+    InvalidArgument(String),
+    Open(BufferError),
+    Write(BufferError),
+    PatternNotFound(String),
+    /// A search or `:s` pattern that isn't a valid regular expression.
+    InvalidPattern(String),
+    /// A pattern with a line break in it, since matches don't span lines.
+    MultiLinePattern(String),
+    /// `n`, `N` or an empty search or `:s` pattern with nothing searched
+    /// for yet.
+    NoPreviousPattern,
+    /// `:s` on its own before any substitution.
+    NoPreviousSubstitute,
+}
+
+impl fmt::Display for ExError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExError::NotACommand(command) => write!(f, "Not an editor command: {}", command),
+            ExError::InvalidRange => write!(f, "Invalid range"),
+            ExError::NoRangeAllowed => write!(f, "No range allowed"),
+            ExError::TrailingCharacters(text) => write!(f, "Trailing characters: {}", text),
+            ExError::NoWriteSinceLastChange(name) => write!(
+                f,
+                "No write since last change for \"{}\" (add ! to override)",
+                name
+            ),
+            ExError::NoFileName => write!(f, "No file name"),
+            ExError::UnknownOption(option) => write!(f, "Unknown option: {}", option),
+            ExError::InvalidArgument(argument) => write!(f, "Invalid argument: {}", argument),
+            ExError::Open(err) => write!(f, "Can't open file: {}", err),
+            ExError::Write(err) => write!(f, "Can't write file: {}", err),
+            ExError::PatternNotFound(pattern) => write!(f, "Pattern not found: {}", pattern),
+            ExError::InvalidPattern(pattern) => write!(f, "Invalid pattern: {}", pattern),
+            ExError::MultiLinePattern(pattern) => {
+                write!(f, "Patterns can't match across lines: {}", pattern)
+            }
+            ExError::NoPreviousPattern => write!(f, "No previous search pattern"),
+            ExError::NoPreviousSubstitute => {
+                write!(f, "No previous substitute regular expression")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExError::Open(err) | ExError::Write(err) => Some(err),
+            _ => None,
+        }
+    }
+}
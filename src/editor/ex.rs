@@ -0,0 +1,473 @@
+use super::error::ExError;
+
+/// Lines from `start` to `end`, both included and counted from 0, that a
+/// command line range like `10,20` or `%` stands for.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn line(line: usize) -> LineRange {
+        LineRange {
+            start: line,
+            end: line,
+        }
+    }
+}
+
+/// A command line split into its parts, like `:10,20d` or `:w! file`.
+#[derive(Debug)]
+pub struct ExCommand<'a> {
+    pub range: Option<LineRange>,
+    /// The name as typed, which may be cut short. Empty for a range alone.
+    pub name: &'a str,
+    /// Whether the name is followed by `!`.
+    pub bang: bool,
+    /// Whatever follows the name, trimmed.
+    pub argument: Option<&'a str>,
+}
+
+impl ExCommand<'_> {
+    /// The range, or the `current` line if none was given.
+    pub fn lines(&self, current: usize) -> LineRange {
+        self.range.unwrap_or_else(|| LineRange::line(current))
+    }
+
+    /// Fails for commands that take no argument if one was given.
+    pub fn no_argument(&self) -> Result<(), ExError> {
+        match self.argument {
+            Some(argument) => Err(ExError::TrailingCharacters(argument.to_owned())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits `text` into a command. Addresses are line numbers, `.` for the
+/// `current` line, `$` for the `last` one, each maybe followed by `+N` or
+/// `-N`, and `%` stands for every line.
+pub fn parse(text: &str, current: usize, last: usize) -> Result<ExCommand<'_>, ExError> {
+    let mut parser = Parser {
+        rest: text.trim_start().trim_start_matches(':'),
+        current,
+        last,
+    };
+    let range = parser.range()?;
+    let rest = parser.rest.trim_start();
+    let name_len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(name_len);
+    let bang = rest.starts_with('!');
+    let rest = if bang { &rest[1..] } else { rest }.trim();
+    Ok(ExCommand {
+        range,
+        name,
+        bang,
+        argument: if rest.is_empty() { None } else { Some(rest) },
+    })
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+    current: usize,
+    last: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn range(&mut self) -> Result<Option<LineRange>, ExError> {
+        if self.eat('%') {
+            return Ok(Some(LineRange {
+                start: 0,
+                end: self.last,
+            }));
+        }
+        let start = match self.address()? {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+        let end = if self.eat(',') || self.eat(';') {
+            self.address()?.ok_or(ExError::InvalidRange)?
+        } else {
+            start
+        };
+        // Like vim, a backwards range is taken the right way round.
+        Ok(Some(LineRange {
+            start: start.min(end),
+            end: start.max(end),
+        }))
+    }
+
+    /// One address, checked to be in the buffer.
+    fn address(&mut self) -> Result<Option<usize>, ExError> {
+        let base = if self.eat('.') {
+            Some(self.current as isize)
+        } else if self.eat('$') {
+            Some(self.last as isize)
+        } else {
+            self.number().map(|line| line as isize - 1)
+        };
+        let mut line = base;
+        loop {
+            let sign = if self.eat('+') {
+                1
+            } else if self.eat('-') {
+                -1
+            } else {
+                break;
+            };
+            let offset = self.number().unwrap_or(1) as isize;
+            line = Some(line.unwrap_or(self.current as isize) + sign * offset);
+        }
+        match line {
+            // `:0` is taken as the first line.
+            Some(line) if line == -1 && base == Some(-1) => Ok(Some(0)),
+            Some(line) if line < 0 || line > self.last as isize => Err(ExError::InvalidRange),
+            Some(line) => Ok(Some(line as usize)),
+            None => Ok(None),
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let len = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let (digits, rest) = self.rest.split_at(len);
+        let number = digits.parse().ok()?;
+        self.rest = rest;
+        Some(number)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.rest.starts_with(c) {
+            self.rest = &self.rest[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What runs a command, given whatever it acts on.
+pub type Handler<T> = fn(&mut T, &ExCommand) -> Result<(), ExError>;
+
+/// What the argument of a command is completed from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Completion {
+    Nothing,
+    Files,
+    Options,
+}
+
+/// A command that can be run from the command line.
+pub struct Definition<T> {
+    /// The full name with the part that can be left off in brackets, like
+    /// `w[rite]`.
+    pub name: &'static str,
+    /// Whether it acts on a range of lines.
+    pub ranged: bool,
+    pub completion: Completion,
+    /// The keys that do the same in normal mode, if any.
+    pub keys: Option<&'static str>,
+    pub run: Handler<T>,
+}
+
+impl<T> Definition<T> {
+    /// Whether `name` is this command's name, or cut short no further than
+    /// the part in brackets.
+    fn matches(&self, name: &str) -> bool {
+        let (required, optional) = match self.name.find('[') {
+            Some(i) => (&self.name[..i], self.name[i + 1..].trim_end_matches(']')),
+            None => (self.name, ""),
+        };
+        name.starts_with(required)
+            && required.len() + optional.len() >= name.len()
+            && optional.starts_with(&name[required.len()..])
+    }
+
+    /// The name without the brackets.
+    fn full_name(&self) -> String {
+        self.name.replace(['[', ']'], "")
+    }
+}
+
+/// The commands the command line knows, in the order they are looked up
+/// in.
+pub struct Commands<T> {
+    definitions: Vec<Definition<T>>,
+}
+
+impl<T> Commands<T> {
+    pub fn new() -> Commands<T> {
+        Commands {
+            definitions: Vec::new(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        ranged: bool,
+        completion: Completion,
+        run: Handler<T>,
+    ) {
+        self.definitions.push(Definition {
+            name,
+            ranged,
+            completion,
+            keys: None,
+            run,
+        });
+    }
+
+    /// Notes that `keys` do the same as the command `name` in normal mode.
+    pub fn bind(&mut self, name: &str, keys: &'static str) {
+        if let Some(definition) = self.definitions.iter_mut().find(|d| d.matches(name)) {
+            definition.keys = Some(keys);
+        }
+    }
+
+    /// The full name of every command with the keys bound to it.
+    pub fn list(&self) -> Vec<(String, Option<&'static str>)> {
+        self.definitions
+            .iter()
+            .map(|definition| (definition.full_name(), definition.keys))
+            .collect()
+    }
+
+    /// The first command `name` is a name of.
+    pub fn find(&self, name: &str) -> Option<&Definition<T>> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.matches(name))
+    }
+
+    /// What `text`, a command line up to the cursor, can be completed to,
+    /// each as the whole of it. The name is completed from the commands,
+    /// and the last word of the argument from the files or `options` as the
+    /// command says.
+    pub fn complete(&self, text: &str, options: &[&str]) -> Vec<String> {
+        let start = text
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let name_len = text[start..]
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(text.len() - start);
+        let name = &text[start..start + name_len];
+        let rest = &text[start + name_len..];
+        if rest.is_empty() {
+            return self
+                .definitions
+                .iter()
+                .map(Definition::full_name)
+                .filter(|full_name| full_name.starts_with(name))
+                .map(|full_name| format!("{}{}", &text[..start], full_name))
+                .collect();
+        }
+
+        let rest = rest.trim_start_matches('!');
+        if !rest.starts_with(' ') {
+            return Vec::new();
+        }
+        let word_start = text.rfind(' ').unwrap() + 1;
+        let (head, word) = text.split_at(word_start);
+        let words = match self.find(name).map(|definition| definition.completion) {
+            Some(Completion::Files) => complete_path(word),
+            Some(Completion::Options) => options
+                .iter()
+                .filter(|option| option.starts_with(word))
+                .map(|option| option.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+        words
+            .into_iter()
+            .map(|word| format!("{}{}", head, word))
+            .collect()
+    }
+}
+
+/// The files and directories `word` is the start of a path to, sorted, with
+/// a `/` after directories. Hidden ones are left out unless `word` names
+/// them with a `.`.
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let entries = match std::fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The range `text` parses to on line 5 of 10.
+    fn range(text: &str) -> Result<Option<(usize, usize)>, ExError> {
+        parse(text, 4, 9).map(|command| command.range.map(|range| (range.start, range.end)))
+    }
+
+    fn run(_: &mut (), _: &ExCommand) -> Result<(), ExError> {
+        Ok(())
+    }
+
+    /// Some of the editor's commands, in the order it registers them.
+    fn commands() -> Commands<()> {
+        let mut commands = Commands::new();
+        commands.register("w[rite]", false, Completion::Files, run);
+        commands.register("wq", false, Completion::Files, run);
+        commands.register("x[it]", false, Completion::Files, run);
+        commands.register("q[uit]", false, Completion::Nothing, run);
+        commands.register("s[ubstitute]", true, Completion::Nothing, run);
+        commands.register("se[t]", false, Completion::Options, run);
+        commands
+    }
+
+    fn find(name: &str) -> Option<&'static str> {
+        commands().find(name).map(|definition| definition.name)
+    }
+
+    #[test]
+    fn addresses() {
+        assert_eq!(range("%d").unwrap(), Some((0, 9)));
+        assert_eq!(range(".").unwrap(), Some((4, 4)));
+        assert_eq!(range("$").unwrap(), Some((9, 9)));
+        assert_eq!(range("5").unwrap(), Some((4, 4)));
+        assert_eq!(range(".+2").unwrap(), Some((6, 6)));
+        assert_eq!(range("+3").unwrap(), Some((7, 7)));
+        assert_eq!(range("-").unwrap(), Some((3, 3)));
+        assert_eq!(range("$-1").unwrap(), Some((8, 8)));
+        assert_eq!(range("1,3d").unwrap(), Some((0, 2)));
+        assert_eq!(range(".;$").unwrap(), Some((4, 9)));
+        assert_eq!(range("d").unwrap(), None);
+    }
+
+    #[test]
+    fn line_0_is_the_first_line() {
+        assert_eq!(range(":0").unwrap(), Some((0, 0)));
+        assert_eq!(range("0,$").unwrap(), Some((0, 9)));
+        assert!(matches!(range("1-1"), Err(ExError::InvalidRange)));
+    }
+
+    #[test]
+    fn backwards_ranges_are_turned_round() {
+        assert_eq!(range("3,1").unwrap(), Some((0, 2)));
+        assert_eq!(range("$,.").unwrap(), Some((4, 9)));
+    }
+
+    #[test]
+    fn lines_outside_the_buffer_are_refused() {
+        for text in &["11", "1,11", ".+6", "-5", "1,"] {
+            assert!(
+                matches!(range(text), Err(ExError::InvalidRange)),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn names_bangs_and_arguments() {
+        let command = parse(" :2,3w! out.txt ", 0, 9).unwrap();
+        assert_eq!(command.range, Some(LineRange { start: 1, end: 2 }));
+        assert_eq!(command.name, "w");
+        assert!(command.bang);
+        assert_eq!(command.argument, Some("out.txt"));
+        assert!(command.no_argument().is_err());
+        let command = parse("d", 0, 9).unwrap();
+        assert_eq!(
+            (command.name, command.bang, command.argument),
+            ("d", false, None)
+        );
+        assert!(command.no_argument().is_ok());
+        assert!(matches!(
+            parse("q extra", 0, 9).unwrap().no_argument(),
+            Err(ExError::TrailingCharacters(_))
+        ));
+    }
+
+    #[test]
+    fn names_can_be_cut_short_to_the_brackets() {
+        assert_eq!(find("s"), Some("s[ubstitute]"));
+        assert_eq!(find("sub"), Some("s[ubstitute]"));
+        assert_eq!(find("se"), Some("se[t]"));
+        assert_eq!(find("set"), Some("se[t]"));
+        assert_eq!(find("w"), Some("w[rite]"));
+        assert_eq!(find("write"), Some("w[rite]"));
+        assert_eq!(find("wq"), Some("wq"));
+        assert_eq!(find("x"), Some("x[it]"));
+        assert_eq!(find("xit"), Some("x[it]"));
+        assert_eq!(find("writes"), None);
+        assert_eq!(find("wr"), Some("w[rite]"));
+        assert_eq!(find("sett"), None);
+        assert_eq!(find(""), None);
+    }
+
+    fn complete(text: &str) -> Vec<String> {
+        commands().complete(text, &["tabstop", "shiftwidth", "tabs"])
+    }
+
+    #[test]
+    fn completes_command_names() {
+        assert_eq!(complete("w"), ["write", "wq"]);
+        assert_eq!(complete(":se"), [":set"]);
+        assert_eq!(complete("1,2s"), ["1,2substitute", "1,2set"]);
+        assert_eq!(complete("x"), ["xit"]);
+        assert!(complete("z").is_empty());
+    }
+
+    #[test]
+    fn completes_arguments_as_the_command_says() {
+        assert_eq!(complete("set ta"), ["set tabstop", "set tabs"]);
+        assert_eq!(complete("se nu ta"), ["se nu tabstop", "se nu tabs"]);
+        assert!(complete("q ta").is_empty());
+        assert!(complete("wq!").is_empty());
+    }
+
+    #[test]
+    fn completes_paths() {
+        let dir = std::env::temp_dir().join(format!("snarkyed-complete-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("alps")).unwrap();
+        std::fs::write(dir.join("alpha.txt"), "").unwrap();
+        std::fs::write(dir.join("beta.txt"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let dir_name = format!("{}/", dir.display());
+
+        assert_eq!(
+            complete_path(&format!("{}al", dir_name)),
+            [
+                format!("{}alpha.txt", dir_name),
+                format!("{}alps/", dir_name)
+            ]
+        );
+        assert_eq!(complete_path(&dir_name).len(), 3);
+        assert_eq!(
+            complete_path(&format!("{}.", dir_name)),
+            [format!("{}.hidden", dir_name)]
+        );
+        assert_eq!(
+            complete(&format!("w! {}b", dir_name)),
+            [format!("w! {}beta.txt", dir_name)]
+        );
+        assert!(complete_path(&format!("{}missing/", dir_name)).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+extern crate unicode_segmentation;
+extern crate unicode_width;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// Columns here are char offsets into a line without its line break, like
+// `Position::col`. The cursor steps over whole extended grapheme clusters,
+// and where things go on screen depends on how many cells each one takes.
+// Tabs stretch to the next multiple of `tabstop`, so their width depends on
+// where they start.
+
+/// A grapheme of a line as laid out on screen.
+struct Cell<'a> {
+    col: usize,
+    text: &'a str,
+    x: usize,
+    width: usize,
+}
+
+impl Cell<'_> {
+    fn end(&self) -> usize {
+        self.col + self.text.chars().count()
+    }
+}
+
+/// The graphemes of `line` along with the column each one starts at.
+fn graphemes(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut col = 0;
+    line.graphemes(true).map(move |grapheme| {
+        let start = col;
+        col += grapheme.chars().count();
+        (start, grapheme)
+    })
+}
+
+/// The graphemes of `line` along with where they go on screen.
+fn cells(line: &str, tabstop: usize) -> impl Iterator<Item = Cell<'_>> {
+    let mut x = 0;
+    graphemes(line).map(move |(col, text)| {
+        let width = width(text, x, tabstop);
+        let cell = Cell {
+            col,
+            text,
+            x,
+            width,
+        };
+        x += width;
+        cell
+    })
+}
+
+/// How many cells `grapheme` takes on screen when drawn at `x`. Even
+/// zero-width ones get a cell, or the cursor would have nowhere to go.
+fn width(grapheme: &str, x: usize, tabstop: usize) -> usize {
+    if grapheme == "\t" {
+        let tabstop = tabstop.max(1);
+        tabstop - x % tabstop
+    } else {
+        grapheme.width().max(1)
+    }
+}
+
+/// The start of the grapheme after the one at `col`, or the end of the line.
+pub fn next_boundary(line: &str, col: usize) -> usize {
+    graphemes(line)
+        .map(|(start, _)| start)
+        .find(|start| *start > col)
+        .unwrap_or_else(|| line.chars().count().max(col))
+}
+
+/// The start of the grapheme before `col`.
+pub fn prev_boundary(line: &str, col: usize) -> usize {
+    graphemes(line)
+        .map(|(start, _)| start)
+        .take_while(|start| *start < col)
+        .last()
+        .unwrap_or(0)
+}
+
+/// The start of the grapheme `col` is in.
+pub fn floor_boundary(line: &str, col: usize) -> usize {
+    prev_boundary(line, col + 1)
+}
+
+/// The screen column `col` is drawn at. Columns past the end of the line
+/// take a cell each.
+pub fn display_col(line: &str, col: usize, tabstop: usize) -> usize {
+    let mut x = 0;
+    let mut len = 0;
+    for cell in cells(line, tabstop) {
+        if cell.col >= col {
+            return cell.x;
+        }
+        x = cell.x + cell.width;
+        len = cell.end();
+    }
+    x + col.saturating_sub(len)
+}
+
+/// How many cells the grapheme at `col` takes, or one past the end of the
+/// line.
+pub fn width_at(line: &str, col: usize, tabstop: usize) -> usize {
+    cells(line, tabstop)
+        .find(|cell| cell.end() > col)
+        .map_or(1, |cell| cell.width)
+}
+
+/// The whole width of `line` on screen.
+pub fn display_width(line: &str, tabstop: usize) -> usize {
+    cells(line, tabstop).map(|cell| cell.width).sum()
+}
+
+/// The start of the grapheme drawn over screen column `x`, or the end of
+/// the line if it is shorter than that.
+pub fn col_at_display(line: &str, x: usize, tabstop: usize) -> usize {
+    cells(line, tabstop)
+        .find(|cell| cell.x + cell.width > x)
+        .map_or_else(|| line.chars().count(), |cell| cell.col)
+}
+
+/// The columns `line` from `start` up to `end` is drawn as, with each tab
+/// turned into `marker` followed by enough spaces to reach the next tab
+/// stop.
+pub fn expand_tabs(line: &str, start: usize, end: usize, tabstop: usize, marker: char) -> String {
+    let mut expanded = String::new();
+    for cell in cells(line, tabstop).filter(|cell| start <= cell.col && cell.col < end) {
+        if cell.text == "\t" {
+            expanded.push(marker);
+            expanded.push_str(&" ".repeat(cell.width - 1));
+        } else {
+            expanded.push_str(cell.text);
+        }
+    }
+    expanded
+}
+
+/// The columns rows start at when `line` is wrapped to `width` screen
+/// columns. A grapheme that doesn't fit at the end of a row goes at the
+/// start of the next one.
+pub fn wrap(line: &str, width: usize, tabstop: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut row_x = 0;
+    for cell in cells(line, tabstop) {
+        if cell.x > row_x && cell.x + cell.width > row_x + width {
+            starts.push(cell.col);
+            row_x = cell.x;
+        }
+    }
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CJK: &str = "a中b";
+    const COMBINING: &str = "e\u{301}x";
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}x";
+    const TABS: &str = "a\tb";
+
+    #[test]
+    fn boundaries_step_over_whole_graphemes() {
+        // (line, col, next, prev)
+        let cases = [
+            ("abc", 1, 2, 0),
+            ("abc", 3, 3, 2),
+            (CJK, 1, 2, 0),
+            (COMBINING, 0, 2, 0),
+            (COMBINING, 2, 3, 0),
+            (FAMILY, 0, 5, 0),
+            (FAMILY, 5, 6, 0),
+            (TABS, 1, 2, 0),
+            ("", 0, 0, 0),
+        ];
+        for (line, col, next, prev) in cases.iter() {
+            assert_eq!(
+                next_boundary(line, *col),
+                *next,
+                "next in {:?} at {}",
+                line,
+                col
+            );
+            assert_eq!(
+                prev_boundary(line, *col),
+                *prev,
+                "prev in {:?} at {}",
+                line,
+                col
+            );
+        }
+        assert_eq!(floor_boundary(COMBINING, 1), 0);
+        assert_eq!(floor_boundary(FAMILY, 3), 0);
+        assert_eq!(floor_boundary(FAMILY, 5), 5);
+    }
+
+    #[test]
+    fn display_columns_count_cells() {
+        // (line, col, screen column it's drawn at)
+        let cases = [
+            ("abc", 2, 2),
+            ("abc", 5, 5),
+            (CJK, 1, 1),
+            (CJK, 2, 3),
+            (CJK, 3, 4),
+            (COMBINING, 2, 1),
+            (FAMILY, 5, 2),
+            (TABS, 1, 1),
+            (TABS, 2, 4),
+            ("\t\tx", 2, 8),
+        ];
+        for (line, col, x) in cases.iter() {
+            assert_eq!(display_col(line, *col, 4), *x, "{:?} at {}", line, col);
+        }
+        assert_eq!(display_width(CJK, 4), 4);
+        assert_eq!(display_width(FAMILY, 4), 3);
+        assert_eq!(width_at(TABS, 1, 4), 3);
+        assert_eq!(width_at(CJK, 1, 4), 2);
+        assert_eq!(width_at(CJK, 9, 4), 1);
+    }
+
+    #[test]
+    fn screen_columns_map_back_to_graphemes() {
+        // (line, screen column, col of the grapheme drawn there)
+        let cases = [
+            (CJK, 0, 0),
+            (CJK, 1, 1),
+            (CJK, 2, 1),
+            (CJK, 3, 2),
+            (CJK, 9, 3),
+            (COMBINING, 1, 2),
+            (FAMILY, 1, 0),
+            (FAMILY, 2, 5),
+            (TABS, 2, 1),
+            (TABS, 3, 1),
+            (TABS, 4, 2),
+        ];
+        for (line, x, col) in cases.iter() {
+            assert_eq!(col_at_display(line, *x, 4), *col, "{:?} at {}", line, x);
+        }
+    }
+
+    #[test]
+    fn wrapping_keeps_graphemes_whole() {
+        // (line, width, tabstop, row starts)
+        let cases: [(&str, usize, usize, &[usize]); 6] = [
+            ("", 4, 8, &[0]),
+            ("abcdef", 4, 8, &[0, 4]),
+            ("ab中cd", 3, 8, &[0, 2, 4]),
+            ("ab\u{1F468}\u{200D}\u{1F469}cd", 3, 8, &[0, 2, 6]),
+            ("ae\u{301}e\u{301}b", 2, 8, &[0, 3]),
+            ("\tab", 4, 8, &[0, 1]),
+        ];
+        for (line, width, tabstop, starts) in cases.iter() {
+            assert_eq!(wrap(line, *width, *tabstop), starts.to_vec(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_stop() {
+        assert_eq!(expand_tabs(TABS, 0, 3, 4, '>'), "a>  b");
+        assert_eq!(expand_tabs("\t\tx", 1, 3, 4, ' '), "    x");
+        assert_eq!(expand_tabs(CJK, 1, 2, 4, ' '), "中");
+    }
+}
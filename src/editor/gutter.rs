@@ -0,0 +1,171 @@
+use super::buffer::Buffer;
+use super::scroll::Row;
+
+/// What a decoration sees of the view it is drawn in.
+pub struct Context<'a> {
+    pub buffer: &'a Buffer,
+    pub current_line: usize,
+    /// Whether line numbers counted from 1 are turned on.
+    pub number: bool,
+    /// Whether line numbers counted from the cursor line are turned on.
+    pub relativenumber: bool,
+}
+
+/// Something shown in the gutter beside each line, like its number. Each
+/// one gets its own columns, side by side.
+pub trait Decoration {
+    /// How many screen columns it takes, or 0 to be left out.
+    fn width(&self, context: &Context) -> usize;
+
+    /// What goes beside `line`, no wider than `width`, or `None` to leave
+    /// it blank.
+    fn mark(&self, context: &Context, line: usize) -> Option<String>;
+}
+
+/// The columns left of the text, made up of the decorations added to it.
+pub struct Gutter {
+    decorations: Vec<Box<dyn Decoration>>,
+}
+
+impl Gutter {
+    pub fn new() -> Gutter {
+        Gutter {
+            decorations: Vec::new(),
+        }
+    }
+
+    pub fn add<D: Decoration + 'static>(&mut self, decoration: D) {
+        self.decorations.push(Box::new(decoration));
+    }
+
+    /// How many screen columns the gutter takes, counting a blank one
+    /// between it and the text.
+    pub fn width(&self, context: &Context) -> usize {
+        match self.decorations.iter().map(|d| d.width(context)).sum() {
+            0 => 0,
+            width => width + 1,
+        }
+    }
+
+    /// The gutter beside `rows`, split into the row of the cursor line and
+    /// the rest so the two can be drawn in different colours. Each has a
+    /// line for every row, blank where the other one goes. Only the first
+    /// row of a wrapped line gets marks.
+    pub fn render(&self, context: &Context, rows: &[Row]) -> (String, String) {
+        let mut current = Vec::new();
+        let mut others = Vec::new();
+        for row in rows {
+            let marks = if row.start == 0 {
+                self.marks(context, row.line)
+            } else {
+                String::new()
+            };
+            if row.line == context.current_line {
+                current.push(marks);
+                others.push(String::new());
+            } else {
+                current.push(String::new());
+                others.push(marks);
+            }
+        }
+        (current.join("\n"), others.join("\n"))
+    }
+
+    fn marks(&self, context: &Context, line: usize) -> String {
+        self.decorations
+            .iter()
+            .filter_map(|decoration| match decoration.width(context) {
+                0 => None,
+                width => {
+                    let mark = decoration.mark(context, line).unwrap_or_default();
+                    Some(format!("{:>width$}", mark, width = width))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Line numbers counted from 1 with `number`, relative to the cursor line
+/// with `relativenumber`, or both with the cursor line's own number shown.
+pub struct LineNumbers;
+
+impl Decoration for LineNumbers {
+    /// Wide enough for the last line's number, which no distance between
+    /// two lines is more than.
+    fn width(&self, context: &Context) -> usize {
+        if context.number || context.relativenumber {
+            context.buffer.get_lines_count().to_string().len()
+        } else {
+            0
+        }
+    }
+
+    fn mark(&self, context: &Context, line: usize) -> Option<String> {
+        let current_line = context.current_line;
+        let number = if context.relativenumber && !(context.number && line == current_line) {
+            line.abs_diff(current_line)
+        } else {
+            line + 1
+        };
+        Some(number.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(line: usize, start: usize) -> Row {
+        Row {
+            line,
+            start,
+            end: start + 1,
+            x: start,
+            last: true,
+        }
+    }
+
+    /// The marks `LineNumbers` puts beside each of the lines of `buffer`
+    /// with the cursor on line 2.
+    fn numbers(buffer: &Buffer, number: bool, relativenumber: bool) -> Vec<String> {
+        let context = Context {
+            buffer,
+            current_line: 2,
+            number,
+            relativenumber,
+        };
+        (0..buffer.get_lines_count())
+            .map(|line| LineNumbers.mark(&context, line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn line_numbers_absolute_relative_or_hybrid() {
+        let buffer = Buffer::from_reader("a\nb\nc\nd".as_bytes()).unwrap();
+        assert_eq!(numbers(&buffer, true, false), ["1", "2", "3", "4"]);
+        assert_eq!(numbers(&buffer, false, true), ["2", "1", "0", "1"]);
+        assert_eq!(numbers(&buffer, true, true), ["2", "1", "3", "1"]);
+    }
+
+    #[test]
+    fn the_gutter_fits_the_last_line_number() {
+        let buffer = Buffer::from_reader("\n".repeat(99).as_bytes()).unwrap();
+        let mut gutter = Gutter::new();
+        gutter.add(LineNumbers);
+        let mut context = Context {
+            buffer: &buffer,
+            current_line: 2,
+            number: true,
+            relativenumber: false,
+        };
+        assert_eq!(gutter.width(&context), 4);
+        let rows = [row(1, 0), row(2, 0), row(2, 4)];
+        assert_eq!(
+            gutter.render(&context, &rows),
+            ("\n  3\n".to_owned(), "  2\n\n".to_owned())
+        );
+        context.number = false;
+        assert_eq!(gutter.width(&context), 0);
+        assert_eq!(gutter.render(&context, &rows).1, "\n\n");
+    }
+}
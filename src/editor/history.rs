@@ -0,0 +1,296 @@
+use super::range::Position;
+
+/// A single replacement in a buffer, in char indices: `removed` was taken
+/// out at `at` and `inserted` put in its place.
+pub struct Edit {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+/// A node in the undo tree: the buffer state reached by applying `edits` to
+/// the state of `parent`.
+struct Revision {
+    parent: usize,
+    last_child: Option<usize>,
+    edits: Vec<Edit>,
+    cursor: Position,
+}
+
+/// Undo tree of a buffer. Revisions are stored in the order they were
+/// created, so an index doubles as a point in time and stepping through the
+/// indexes walks every state the buffer has been in, across branches.
+pub struct History {
+    revisions: Vec<Revision>,
+    current: usize,
+    group_open: bool,
+    group_cursor: Position,
+    group_revision: Option<usize>,
+    group_held: bool,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            revisions: vec![Revision {
+                parent: 0,
+                last_child: None,
+                edits: Vec::new(),
+                cursor: Position::new(0, 0),
+            }],
+            current: 0,
+            group_open: false,
+            group_cursor: Position::new(0, 0),
+            group_revision: None,
+            group_held: false,
+        }
+    }
+
+    /// The revision the buffer is at.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Starts collecting edits into a single undo step. `cursor` is where
+    /// the cursor goes back to when the step is undone.
+    pub fn begin_group(&mut self, cursor: Position) {
+        if !self.group_open {
+            self.group_open = true;
+            self.group_cursor = cursor;
+            self.group_revision = None;
+        }
+    }
+
+    pub fn end_group(&mut self) {
+        if self.group_held {
+            return;
+        }
+        self.group_open = false;
+        self.group_revision = None;
+    }
+
+    /// Makes the next edit start a new undo step even inside a group, so
+    /// no step holds edits from both before and after a save.
+    pub fn split_group(&mut self) {
+        self.group_revision = None;
+    }
+
+    /// While held, `end_group` leaves the group open, so a run of commands
+    /// that each end their own group still undoes as one step.
+    pub fn hold_group(&mut self, held: bool) {
+        self.group_held = held;
+    }
+
+    /// Records an edit made at `start`. Outside a group every edit is its
+    /// own undo step.
+    pub fn record(&mut self, edit: Edit, start: Position) {
+        let revision = match self.group_revision {
+            Some(revision) if self.group_open => revision,
+            _ => {
+                let cursor = if self.group_open {
+                    self.group_cursor
+                } else {
+                    start
+                };
+                let revision = self.push_revision(cursor);
+                if self.group_open {
+                    self.group_revision = Some(revision);
+                }
+                revision
+            }
+        };
+        self.revisions[revision].edits.push(edit);
+    }
+
+    fn push_revision(&mut self, cursor: Position) -> usize {
+        let revision = self.revisions.len();
+        self.revisions.push(Revision {
+            parent: self.current,
+            last_child: None,
+            edits: Vec::new(),
+            cursor,
+        });
+        self.revisions[self.current].last_child = Some(revision);
+        self.current = revision;
+        revision
+    }
+
+    /// Steps back to the parent revision, returning the one to revert.
+    pub fn undo(&mut self) -> Option<usize> {
+        self.end_group();
+        if self.current == 0 {
+            return None;
+        }
+        let undone = self.current;
+        self.current = self.revisions[undone].parent;
+        self.revisions[self.current].last_child = Some(undone);
+        Some(undone)
+    }
+
+    /// Steps forward to the most recently visited child, returning the
+    /// revision to apply.
+    pub fn redo(&mut self) -> Option<usize> {
+        self.end_group();
+        let child = self.revisions[self.current].last_child?;
+        self.current = child;
+        Some(child)
+    }
+
+    /// Moves to `target`, returning the revisions to revert (in order) and
+    /// then the ones to apply (in order) to get there from the current one.
+    pub fn travel(&mut self, target: usize) -> (Vec<usize>, Vec<usize>) {
+        self.end_group();
+        let target = target.min(self.revisions.len() - 1);
+        let target_path = self.path_from_root(target);
+        let mut reverts = Vec::new();
+        let mut revision = self.current;
+        while !target_path.contains(&revision) {
+            reverts.push(revision);
+            revision = self.revisions[revision].parent;
+        }
+        let fork = target_path.iter().position(|r| *r == revision).unwrap();
+        let applies = target_path[fork + 1..].to_vec();
+        for r in reverts.iter() {
+            let parent = self.revisions[*r].parent;
+            self.revisions[parent].last_child = Some(*r);
+        }
+        for r in applies.iter() {
+            let parent = self.revisions[*r].parent;
+            self.revisions[parent].last_child = Some(*r);
+        }
+        self.current = target;
+        (reverts, applies)
+    }
+
+    pub fn revision_count(&self) -> usize {
+        self.revisions.len()
+    }
+
+    pub fn edits(&self, revision: usize) -> &[Edit] {
+        &self.revisions[revision].edits
+    }
+
+    pub fn cursor(&self, revision: usize) -> Position {
+        self.revisions[revision].cursor
+    }
+
+    fn path_from_root(&self, revision: usize) -> Vec<usize> {
+        let mut path = vec![revision];
+        let mut revision = revision;
+        while revision != 0 {
+            revision = self.revisions[revision].parent;
+            path.push(revision);
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::buffer::Buffer;
+
+    fn edit(at: usize, inserted: &str) -> Edit {
+        Edit {
+            at,
+            removed: String::new(),
+            inserted: inserted.to_owned(),
+        }
+    }
+
+    fn record(history: &mut History, at: usize, inserted: &str) {
+        history.record(edit(at, inserted), Position::new(0, at));
+    }
+
+    #[test]
+    fn edits_outside_a_group_are_separate_steps() {
+        let mut history = History::new();
+        record(&mut history, 0, "a");
+        record(&mut history, 1, "b");
+        assert_eq!(history.current(), 2);
+        assert_eq!(history.cursor(2), Position::new(0, 1));
+    }
+
+    #[test]
+    fn a_group_is_one_step() {
+        let mut history = History::new();
+        history.begin_group(Position::new(3, 4));
+        record(&mut history, 0, "a");
+        record(&mut history, 1, "b");
+        history.end_group();
+        assert_eq!(history.current(), 1);
+        assert_eq!(history.edits(1).len(), 2);
+        assert_eq!(history.cursor(1), Position::new(3, 4));
+        record(&mut history, 2, "c");
+        assert_eq!(history.current(), 2);
+    }
+
+    #[test]
+    fn a_held_group_stays_open() {
+        let mut history = History::new();
+        history.hold_group(true);
+        history.begin_group(Position::new(0, 0));
+        record(&mut history, 0, "a");
+        history.end_group();
+        history.begin_group(Position::new(5, 5));
+        record(&mut history, 1, "b");
+        history.hold_group(false);
+        history.end_group();
+        assert_eq!(history.current(), 1);
+        assert_eq!(history.edits(1).len(), 2);
+        assert_eq!(history.cursor(1), Position::new(0, 0));
+    }
+
+    #[test]
+    fn undo_and_redo_put_the_cursor_back() {
+        let mut buffer = Buffer::from_reader("abc".as_bytes()).unwrap();
+        buffer.begin_undo_group(Position::new(0, 1));
+        buffer.insert(Position::new(0, 1), "x");
+        buffer.insert(Position::new(0, 2), "y");
+        buffer.end_undo_group();
+        assert_eq!(buffer.line_text(0), "axybc");
+        assert_eq!(buffer.undo(), Some(Position::new(0, 1)));
+        assert_eq!(buffer.line_text(0), "abc");
+        assert_eq!(buffer.undo(), None);
+        assert_eq!(buffer.redo(), Some(Position::new(0, 1)));
+        assert_eq!(buffer.line_text(0), "axybc");
+        assert_eq!(buffer.redo(), None);
+    }
+
+    #[test]
+    fn travel_reaches_an_abandoned_branch() {
+        let mut history = History::new();
+        record(&mut history, 0, "a");
+        record(&mut history, 1, "b");
+        assert_eq!(history.undo(), Some(2));
+        record(&mut history, 1, "c");
+        assert_eq!(history.current(), 3);
+
+        assert_eq!(history.travel(2), (vec![3], vec![2]));
+        assert_eq!(history.current(), 2);
+        assert_eq!(history.travel(3), (vec![2], vec![3]));
+        assert_eq!(history.travel(0), (vec![3, 1], vec![]));
+        // Redo follows the branch last travelled along.
+        assert_eq!(history.redo(), Some(1));
+        assert_eq!(history.redo(), Some(3));
+    }
+
+    #[test]
+    fn buffer_travel_restores_each_state() {
+        let mut buffer = Buffer::from_reader("".as_bytes()).unwrap();
+        buffer.insert(Position::new(0, 0), "a");
+        buffer.insert(Position::new(0, 1), "b");
+        buffer.undo();
+        buffer.insert(Position::new(0, 1), "c");
+        assert_eq!(buffer.line_text(0), "ac");
+        buffer.travel(-1);
+        assert_eq!(buffer.line_text(0), "ab");
+        buffer.travel(-1);
+        assert_eq!(buffer.line_text(0), "a");
+        buffer.travel(-1);
+        assert_eq!(buffer.line_text(0), "");
+        buffer.travel(3);
+        assert_eq!(buffer.line_text(0), "ac");
+    }
+}
@@ -0,0 +1,467 @@
+use std::time::{Duration, Instant};
+
+use super::command::{Command, Operator, Target};
+use super::mode::{Mode, VisualKind};
+use super::motion::Motion;
+use super::register::Registers;
+use super::search::Direction;
+use super::text_object::TextObject;
+
+/// How long a partial key sequence waits for the next key.
+const TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    CtrlUp,
+    CtrlDown,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Parse {
+    /// The keys so far are the start of a longer sequence.
+    Pending,
+    /// The keys resolved to a command.
+    Done(Args, Command),
+    /// The keys don't mean anything and were dropped.
+    Invalid,
+}
+
+/// The count and register typed before a command, as in `"a3yy`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Args {
+    pub count: Option<usize>,
+    pub register: Option<char>,
+}
+
+enum Lookup<T> {
+    Found(T),
+    Prefix,
+    NoMatch,
+}
+
+/// Collects normal mode keys until they form a complete command, such as
+/// `5j`, `gg` or `3dw`.
+pub struct KeyParser {
+    keys: Vec<Key>,
+    last_key: Option<Instant>,
+}
+
+impl KeyParser {
+    pub fn new() -> KeyParser {
+        KeyParser {
+            keys: Vec::new(),
+            last_key: None,
+        }
+    }
+
+    /// Adds a key typed in `mode` to the sequence.
+    pub fn feed(&mut self, key: Key, mode: Mode, now: Instant) -> Parse {
+        self.expire(now);
+        self.keys.push(key);
+        self.last_key = Some(now);
+        let parse = parse(&self.keys, mode);
+        if parse != Parse::Pending {
+            self.keys.clear();
+        }
+        parse
+    }
+
+    /// Drops a partial sequence once no key was typed for a while.
+    pub fn expire(&mut self, now: Instant) {
+        if let Some(last_key) = self.last_key {
+            if now.duration_since(last_key) > TIMEOUT {
+                self.keys.clear();
+                self.last_key = None;
+            }
+        }
+    }
+}
+
+/// Resolves a whole key sequence: `[count] ["x] [count] command` or
+/// `[count] ["x] [count] operator [count] (motion | text object | operator)`.
+/// In visual mode an operator acts on the selection straight away instead.
+pub fn parse(keys: &[Key], mode: Mode) -> Parse {
+    let (count, keys) = take_count(keys);
+    let (register, keys) = match keys {
+        [Key::Char('"')] => return Parse::Pending,
+        [Key::Char('"'), Key::Char(name), rest @ ..] if Registers::is_valid_name(*name) => {
+            (Some(*name), rest)
+        }
+        [Key::Char('"'), ..] => return Parse::Invalid,
+        _ => (None, keys),
+    };
+    let (register_count, keys) = take_count(keys);
+    let count = multiply(count, register_count);
+    let args = Args { count, register };
+    if keys.is_empty() {
+        return Parse::Pending;
+    }
+    if let Mode::Visual(kind) = mode {
+        return parse_visual(keys, args, kind);
+    }
+    if let Some(operator) = operator(keys[0]) {
+        let (motion_count, rest) = take_count(&keys[1..]);
+        let count = multiply(count, motion_count);
+        return match rest {
+            [] => Parse::Pending,
+            [key] if *key == keys[0] => {
+                Parse::Done(Args { count, ..args }, Command::OperateLines(operator))
+            }
+            _ => match target(rest) {
+                Lookup::Found(target) => {
+                    Parse::Done(Args { count, ..args }, Command::Operate(operator, target))
+                }
+                Lookup::Prefix => Parse::Pending,
+                Lookup::NoMatch => Parse::Invalid,
+            },
+        };
+    }
+    match (motion(keys), command(keys)) {
+        (Lookup::Found(motion), _) => Parse::Done(args, Command::Move(motion)),
+        (_, Lookup::Found(command)) => Parse::Done(args, command),
+        (Lookup::Prefix, _) | (_, Lookup::Prefix) => Parse::Pending,
+        _ => Parse::Invalid,
+    }
+}
+
+fn parse_visual(keys: &[Key], args: Args, kind: VisualKind) -> Parse {
+    if let [key] = keys {
+        if let Some(operator) = operator(*key) {
+            return Parse::Done(args, Command::OperateSelection(operator));
+        }
+    }
+    match (target(keys), visual_command(keys, kind)) {
+        (Lookup::Found(Target::Motion(motion)), _) => Parse::Done(args, Command::Move(motion)),
+        (Lookup::Found(Target::Object(object)), _) => {
+            Parse::Done(args, Command::SelectObject(object))
+        }
+        (_, Lookup::Found(command)) => Parse::Done(args, command),
+        (Lookup::Prefix, _) | (_, Lookup::Prefix) => Parse::Pending,
+        _ => Parse::Invalid,
+    }
+}
+
+/// Splits a leading count off `keys`. A `0` can't start a count since it
+/// is a motion of its own.
+fn take_count(keys: &[Key]) -> (Option<usize>, &[Key]) {
+    let mut count: Option<usize> = None;
+    let mut taken = 0;
+    for key in keys {
+        match key {
+            Key::Char(c) if c.is_ascii_digit() && (count.is_some() || *c != '0') => {
+                let digit = c.to_digit(10).unwrap() as usize;
+                count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                taken += 1;
+            }
+            _ => break,
+        }
+    }
+    (count, &keys[taken..])
+}
+
+fn multiply(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn operator(key: Key) -> Option<Operator> {
+    match key {
+        Key::Char('d') => Some(Operator::Delete),
+        Key::Char('c') => Some(Operator::Change),
+        Key::Char('y') => Some(Operator::Yank),
+        Key::Char('>') => Some(Operator::Indent),
+        Key::Char('<') => Some(Operator::Outdent),
+        _ => None,
+    }
+}
+
+/// What follows an operator: a motion or a text object.
+fn target(keys: &[Key]) -> Lookup<Target> {
+    match keys {
+        [Key::Char('i')] | [Key::Char('a')] => Lookup::Prefix,
+        [Key::Char(prefix), Key::Char(c)] if *prefix == 'i' || *prefix == 'a' => {
+            match TextObject::from_char(*c, *prefix == 'i') {
+                Some(object) => Lookup::Found(Target::Object(object)),
+                None => Lookup::NoMatch,
+            }
+        }
+        _ => match motion(keys) {
+            Lookup::Found(motion) => Lookup::Found(Target::Motion(motion)),
+            Lookup::Prefix => Lookup::Prefix,
+            Lookup::NoMatch => Lookup::NoMatch,
+        },
+    }
+}
+
+fn motion(keys: &[Key]) -> Lookup<Motion> {
+    use self::Key::*;
+    let motion = match keys {
+        [Char('h')] | [Left] => Motion::Left,
+        [Char('l')] | [Right] => Motion::Right,
+        [Char('j')] | [Down] => Motion::Down,
+        [Char('k')] | [Up] => Motion::Up,
+        [Char('0')] => Motion::LineStart,
+        [Char('^')] => Motion::FirstNonBlank,
+        [Char('$')] => Motion::LineEnd,
+        [Char('w')] => Motion::NextWordStart(false),
+        [Char('W')] => Motion::NextWordStart(true),
+        [Char('b')] => Motion::PrevWordStart(false),
+        [Char('B')] => Motion::PrevWordStart(true),
+        [Char('e')] => Motion::WordEnd(false),
+        [Char('E')] => Motion::WordEnd(true),
+        [Char('G')] => Motion::LastLine,
+        [Char('g'), Char('g')] => Motion::FirstLine,
+        [Char('g'), Char('j')] => Motion::RowDown,
+        [Char('g'), Char('k')] => Motion::RowUp,
+        [Char('g')] => return Lookup::Prefix,
+        _ => return Lookup::NoMatch,
+    };
+    Lookup::Found(motion)
+}
+
+fn command(keys: &[Key]) -> Lookup<Command> {
+    use self::Key::*;
+    let command = match keys {
+        [Char('i')] => Command::InsertBefore,
+        [Char('I')] => Command::InsertAtBol,
+        [Char('a')] => Command::Append,
+        [Char('A')] => Command::AppendAtEol,
+        [Char('o')] => Command::OpenBelow,
+        [Char('O')] => Command::OpenAbove,
+        [Char('x')] => Command::DeleteChar,
+        [Char('D')] => Command::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        [Char('C')] => Command::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        [Char('s')] => Command::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        [Char('S')] => Command::OperateLines(Operator::Change),
+        [Char('Y')] => Command::OperateLines(Operator::Yank),
+        [Char('p')] => Command::Paste(false),
+        [Char('P')] => Command::Paste(true),
+        [Char('J')] => Command::JoinLines,
+        [Char('u')] => Command::Undo,
+        [Ctrl('r')] => Command::Redo,
+        [Char('g'), Char('-')] => Command::Earlier,
+        [Char('g'), Char('+')] => Command::Later,
+        [Ctrl('j')] => Command::ScrollDown,
+        [Ctrl('k')] => Command::ScrollUp,
+        [Char('z'), Char('h')] => Command::ScrollLeft,
+        [Char('z'), Char('l')] => Command::ScrollRight,
+        [Char('z'), Char('s')] => Command::ScrollCursorToStart,
+        [Char('z'), Char('e')] => Command::ScrollCursorToEnd,
+        [Char('z')] => return Lookup::Prefix,
+        [Ctrl('s')] => Command::Save,
+        [Char(':')] => Command::EnterCmdline,
+        [Char('/')] => Command::Search(Direction::Forward),
+        [Char('?')] => Command::Search(Direction::Backward),
+        [Char('n')] => Command::SearchNext(false),
+        [Char('N')] => Command::SearchNext(true),
+        [Char('*')] => Command::SearchWord(Direction::Forward),
+        [Char('#')] => Command::SearchWord(Direction::Backward),
+        [Char('v')] => Command::Visual(VisualKind::Char),
+        [Char('V')] => Command::Visual(VisualKind::Line),
+        [Ctrl('v')] => Command::Visual(VisualKind::Block),
+        [Ctrl('n')] => Command::AddCursorAtNextMatch,
+        [CtrlDown] => Command::AddCursorBelow,
+        [CtrlUp] => Command::AddCursorAbove,
+        [Escape] => Command::SingleCursor,
+        [Char('g')] => return Lookup::Prefix,
+        _ => return Lookup::NoMatch,
+    };
+    Lookup::Found(command)
+}
+
+/// The visual mode commands that aren't motions, text objects or operators.
+fn visual_command(keys: &[Key], kind: VisualKind) -> Lookup<Command> {
+    use self::Key::*;
+    let command = match keys {
+        [Escape] => Command::LeaveVisual,
+        [Char('v')] => Command::Visual(VisualKind::Char),
+        [Char('V')] => Command::Visual(VisualKind::Line),
+        [Ctrl('v')] => Command::Visual(VisualKind::Block),
+        [Char('o')] => Command::SwapAnchor,
+        [Char('n')] => Command::SearchNext(false),
+        [Char('N')] => Command::SearchNext(true),
+        [Char('x')] => Command::OperateSelection(Operator::Delete),
+        [Char('s')] => Command::OperateSelection(Operator::Change),
+        [Char('I')] if kind == VisualKind::Block => Command::BlockInsert(false),
+        [Char('A')] if kind == VisualKind::Block => Command::BlockInsert(true),
+        [Ctrl('j')] => Command::ScrollDown,
+        [Ctrl('k')] => Command::ScrollUp,
+        [Char('z'), Char('h')] => Command::ScrollLeft,
+        [Char('z'), Char('l')] => Command::ScrollRight,
+        [Char('z'), Char('s')] => Command::ScrollCursorToStart,
+        [Char('z'), Char('e')] => Command::ScrollCursorToEnd,
+        [Char('z')] => return Lookup::Prefix,
+        _ => return Lookup::NoMatch,
+    };
+    Lookup::Found(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    fn normal(text: &str) -> Parse {
+        parse(&keys(text), Mode::Normal)
+    }
+
+    fn args(count: Option<usize>, register: Option<char>) -> Args {
+        Args { count, register }
+    }
+
+    #[test]
+    fn motions_with_counts() {
+        assert_eq!(
+            normal("5j"),
+            Parse::Done(args(Some(5), None), Command::Move(Motion::Down))
+        );
+        assert_eq!(
+            normal("gg"),
+            Parse::Done(args(None, None), Command::Move(Motion::FirstLine))
+        );
+        assert_eq!(
+            normal("0"),
+            Parse::Done(args(None, None), Command::Move(Motion::LineStart))
+        );
+        assert_eq!(
+            normal("10j"),
+            Parse::Done(args(Some(10), None), Command::Move(Motion::Down))
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            normal("dd"),
+            Parse::Done(args(None, None), Command::OperateLines(Operator::Delete))
+        );
+        let delete_words = Command::Operate(
+            Operator::Delete,
+            Target::Motion(Motion::NextWordStart(false)),
+        );
+        assert_eq!(
+            normal("3dw"),
+            Parse::Done(args(Some(3), None), delete_words)
+        );
+        assert_eq!(
+            normal("2d3w"),
+            Parse::Done(args(Some(6), None), delete_words)
+        );
+        assert_eq!(
+            normal("diw"),
+            Parse::Done(
+                args(None, None),
+                Command::Operate(
+                    Operator::Delete,
+                    Target::Object(TextObject::from_char('w', true).unwrap())
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(
+            normal("\"a3yy"),
+            Parse::Done(
+                args(Some(3), Some('a')),
+                Command::OperateLines(Operator::Yank)
+            )
+        );
+        assert_eq!(
+            normal("\"_dd"),
+            Parse::Done(
+                args(None, Some('_')),
+                Command::OperateLines(Operator::Delete)
+            )
+        );
+        assert_eq!(normal("\""), Parse::Pending);
+        assert_eq!(normal("\"!"), Parse::Invalid);
+    }
+
+    #[test]
+    fn pending_and_invalid_sequences() {
+        assert_eq!(normal("g"), Parse::Pending);
+        assert_eq!(normal("3"), Parse::Pending);
+        assert_eq!(normal("d"), Parse::Pending);
+        assert_eq!(normal("di"), Parse::Pending);
+        assert_eq!(normal("gq"), Parse::Invalid);
+        assert_eq!(normal("dq"), Parse::Invalid);
+        assert_eq!(normal("diq"), Parse::Invalid);
+        assert_eq!(normal("Q"), Parse::Invalid);
+    }
+
+    #[test]
+    fn visual_operators_act_on_the_selection() {
+        assert_eq!(
+            parse(&keys("d"), Mode::Visual(VisualKind::Char)),
+            Parse::Done(
+                args(None, None),
+                Command::OperateSelection(Operator::Delete)
+            )
+        );
+    }
+
+    #[test]
+    fn parser_collects_keys_until_a_command() {
+        let mut parser = KeyParser::new();
+        let now = Instant::now();
+        assert_eq!(
+            parser.feed(Key::Char('g'), Mode::Normal, now),
+            Parse::Pending
+        );
+        assert_eq!(
+            parser.feed(Key::Char('g'), Mode::Normal, now),
+            Parse::Done(args(None, None), Command::Move(Motion::FirstLine))
+        );
+        assert_eq!(
+            parser.feed(Key::Char('Q'), Mode::Normal, now),
+            Parse::Invalid
+        );
+        assert_eq!(
+            parser.feed(Key::Char('j'), Mode::Normal, now),
+            Parse::Done(args(None, None), Command::Move(Motion::Down))
+        );
+    }
+
+    #[test]
+    fn partial_sequences_expire() {
+        let mut parser = KeyParser::new();
+        let now = Instant::now();
+        assert_eq!(
+            parser.feed(Key::Char('d'), Mode::Normal, now),
+            Parse::Pending
+        );
+        let later = now + TIMEOUT + Duration::from_millis(1);
+        assert_eq!(
+            parser.feed(Key::Char('j'), Mode::Normal, later),
+            Parse::Done(args(None, None), Command::Move(Motion::Down))
+        );
+
+        assert_eq!(
+            parser.feed(Key::Char('d'), Mode::Normal, later),
+            Parse::Pending
+        );
+        let soon = later + TIMEOUT / 2;
+        assert_eq!(
+            parser.feed(Key::Char('j'), Mode::Normal, soon),
+            Parse::Done(
+                args(None, None),
+                Command::Operate(Operator::Delete, Target::Motion(Motion::Down))
+            )
+        );
+    }
+}
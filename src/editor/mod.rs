@@ -1,33 +1,118 @@
 mod buffer;
+mod command;
 mod cursor;
+mod error;
+mod ex;
+pub mod grapheme;
+mod gutter;
+mod history;
+mod keymap;
+mod mode;
+mod motion;
+mod options;
+mod range;
+mod register;
+mod scroll;
+mod search;
+mod substitute;
+mod text_object;
 
 use glium::glutin::{ElementState, ModifiersState, VirtualKeyCode};
 use glium::{Display, Frame};
 use glium_glyph::glyph_brush::rusttype::Rect;
 use glium_glyph::glyph_brush::{rusttype::Font, GlyphCruncher, Section};
 use glium_glyph::GlyphBrush;
+use std::time::Instant;
 
 use buffer::Buffer;
+use command::{Command, Operator, Target};
 use cursor::Cursor;
+use error::ExError;
+use ex::{Commands, Completion, ExCommand};
+use gutter::{Decoration, Gutter, LineNumbers};
+use keymap::{Args, Key, KeyParser, Parse};
+use mode::{BlockInsert, Mode, VisualKind};
+use motion::{Motion, MotionKind};
+use range::{Position, Range, Span};
+use register::{Register, RegisterKind, Registers};
+use scroll::{Row, Scroll};
+use search::{Direction, Incsearch, Search};
+use substitute::{Substitute, Substitution};
+use text_object::TextObject;
 
-use crate::constants::{BASE_FONT_SIZE, CTRL_HOLD, NO_MODIFIERS, SHIFT_HOLD};
-use crate::layout_manager::View;
+use crate::constants::{
+    BASE_FONT_SIZE, CTRL_HOLD, NO_MODIFIERS, NUMBER, RELATIVE_NUMBER, SCROLL_OFF, SIDE_SCROLL_OFF,
+    STATUS_LINE_HEIGHT, WRAP,
+};
+use crate::layout_manager::{Action, View};
+use crate::palette::Entry;
+use crate::statusline::{Segment, Side};
 use crate::ui;
 
+/// The options `:set` takes, as they are completed.
+const OPTIONS: &[&str] = &[
+    "expandtab",
+    "noexpandtab",
+    "number",
+    "nonumber",
+    "relativenumber",
+    "norelativenumber",
+    "scrolloff",
+    "shiftwidth",
+    "sidescrolloff",
+    "tabstop",
+    "wrap",
+    "nowrap",
+];
+
 pub struct EditorView<'a, 'b> {
     pub buffer: Buffer,
+    hidden_buffers: Vec<Buffer>,
+    /// The main cursor, or the one commands are being applied to when
+    /// there are several.
     cursor: Cursor,
+    other_cursors: Vec<Cursor>,
     glyph_brush: GlyphBrush<'a, 'b>,
     padding: f32,
     font_size: f32,
-    offset_y: usize,
-    viewport_rows: usize,
+    scroll: Scroll,
+    /// The columns left of the text, with the decorations added to it.
+    gutter: Gutter,
+    /// Whether the gutter shows line numbers counted from 1.
+    number: bool,
+    /// Whether the gutter shows line numbers counted from the cursor line.
+    /// With `number` too, the cursor line shows its own number.
+    relativenumber: bool,
     letter_size: Rect<f32>,
-    last_column: i32,
+    mode: Mode,
+    block_insert: Option<BlockInsert>,
+    keys: KeyParser,
+    registers: Registers,
+    title: String,
+    ex_commands: Commands<Self>,
+    /// The last search, which `n` and `N` repeat.
+    search: Option<Search>,
+    /// The search being typed into the command line.
+    incsearch: Option<Incsearch>,
+    /// Where the search being typed matches, or the match a `:s` with the
+    /// confirm flag is asking about.
+    current_match: Option<Range>,
+    /// Whether the matches of the search on screen are highlighted.
+    hlsearch: bool,
+    /// The last `:s`, which `:s` on its own repeats.
+    last_substitute: Option<Substitute>,
+    /// A `:s` with the confirm flag waiting for an answer about a match,
+    /// with what it'd be replaced with.
+    confirming: Option<(Substitution, Range, String)>,
+    /// What was last sent to the status line.
+    status: Vec<Segment>,
+    actions: Vec<Action>,
 }
 
 impl<'a, 'b> EditorView<'a, 'b> {
-    pub fn new(file: &str, display: &Display) -> EditorView<'a, 'b> {
+    /// Creates an editor with a buffer for each of `files`, showing the
+    /// first one. A file named `-` is read from stdin.
+    pub fn new(files: &[String], display: &Display) -> EditorView<'a, 'b> {
         let font_regular: &[u8] = include_bytes!("../../assets/haskplex.ttf");
         let fonts = vec![Font::from_bytes(font_regular).unwrap()];
         let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
@@ -41,93 +126,2053 @@ impl<'a, 'b> EditorView<'a, 'b> {
             })
             .unwrap();
 
-        EditorView {
-            buffer: Buffer::new(file),
-            cursor: Cursor::new(),
+        let mut actions = Vec::new();
+        let mut buffers: Vec<Buffer> = Vec::new();
+        for file in files {
+            let buffer = if file == "-" {
+                Buffer::from_reader(std::io::stdin())
+            } else {
+                Buffer::open(file)
+            };
+            match buffer {
+                Ok(buffer) => {
+                    if buffer.is_read_only() {
+                        actions.push(read_only_notice(file));
+                    }
+                    buffers.push(buffer);
+                }
+                Err(err) => {
+                    actions.push(Action::ShowMessage(format!("Can't open file: {}", err)));
+                    buffers.push(Buffer::empty());
+                }
+            }
+        }
+        if buffers.is_empty() {
+            buffers.push(Buffer::empty());
+        }
+        let buffer = buffers.remove(0);
+
+        let mut view = EditorView {
+            buffer,
+            hidden_buffers: buffers,
+            cursor: Cursor::new(Position::new(0, 0)),
+            other_cursors: Vec::new(),
             glyph_brush: gb,
             padding: 30.0,
-            font_size: font_size,
-            offset_y: 0,
-            viewport_rows: 0,
-            letter_size: letter_size,
-            last_column: -1,
+            font_size,
+            scroll: Scroll::new(SCROLL_OFF, SIDE_SCROLL_OFF, WRAP),
+            gutter: Gutter::new(),
+            number: NUMBER,
+            relativenumber: RELATIVE_NUMBER,
+            letter_size,
+            mode: Mode::Normal,
+            block_insert: None,
+            keys: KeyParser::new(),
+            registers: Registers::new(),
+            title: String::new(),
+            ex_commands: Self::ex_commands(),
+            search: None,
+            incsearch: None,
+            current_match: None,
+            hlsearch: false,
+            last_substitute: None,
+            confirming: None,
+            status: Vec::new(),
+            actions,
+        };
+        view.add_decoration(LineNumbers);
+        view
+    }
+
+    /// Adds `decoration` to the gutter, right of the ones already there.
+    pub fn add_decoration<D: Decoration + 'static>(&mut self, decoration: D) {
+        self.gutter.add(decoration);
+    }
+
+    fn current_line(&self) -> usize {
+        self.cursor.pos.line
+    }
+
+    fn cursor_position(&self) -> Position {
+        self.cursor.pos
+    }
+
+    /// Moves the cursor to the start of `line`, counted from 1 like in
+    /// the `+N` command line argument, and scrolls it to the top.
+    pub fn goto_line(&mut self, line: usize) {
+        let last_line = self.buffer.get_lines_count() - 1;
+        let line = line.saturating_sub(1).min(last_line);
+        self.scroll.top = line;
+        self.cursor = Cursor::new(Position::new(line, 0));
+        self.other_cursors.clear();
+    }
+
+    /// Puts the cursor on `pos`, or as close to it as the line allows.
+    fn set_cursor_position(&mut self, pos: Position) {
+        let text = self.buffer.line_text(pos.line);
+        self.cursor.pos = pos;
+        self.cursor.pos.col = grapheme::floor_boundary(&text, pos.col.min(self.max_column()));
+        self.cursor.goal_col = None;
+    }
+
+    /// Makes `cursor` the one commands apply to, pulled back into the
+    /// buffer if edits made through other cursors left it outside.
+    fn use_cursor(&mut self, cursor: Cursor) {
+        self.cursor = cursor;
+        self.cursor.pos = self.buffer.clamp(cursor.pos);
+        self.clamp_cursor();
+    }
+
+    /// Runs `f` with each cursor in turn, from the top of the buffer down,
+    /// moving the others along with the edits it makes. Cursors that end up
+    /// in the same place are merged, and the edits undo as a single step.
+    fn for_each_cursor<F: FnMut(&mut Self)>(&mut self, mut f: F) {
+        if self.other_cursors.is_empty() {
+            f(self);
+            self.buffer.take_changes();
+            return;
+        }
+        let main = self.cursor;
+        let mut cursors: Vec<(Cursor, bool)> =
+            self.other_cursors.drain(..).map(|c| (c, false)).collect();
+        cursors.push((main, true));
+        cursors.sort_by_key(|(cursor, _)| cursor.pos);
+
+        self.buffer.begin_undo_group(main.pos);
+        self.buffer.hold_undo_group(true);
+        self.buffer.take_changes();
+        for i in 0..cursors.len() {
+            self.use_cursor(cursors[i].0);
+            f(self);
+            cursors[i].0 = self.cursor;
+            for change in self.buffer.take_changes() {
+                for (j, (cursor, _)) in cursors.iter_mut().enumerate() {
+                    if j != i {
+                        cursor.pos = change.map(cursor.pos);
+                        cursor.anchor = change.map(cursor.anchor);
+                    }
+                }
+            }
+        }
+        self.buffer.hold_undo_group(false);
+        if self.mode != Mode::Insert {
+            self.buffer.end_undo_group();
+        }
+
+        cursors.sort_by_key(|(cursor, _)| cursor.pos);
+        cursors.dedup_by(|(later, later_main), (kept, kept_main)| {
+            if later.pos != kept.pos {
+                return false;
+            }
+            *kept_main |= *later_main;
+            true
+        });
+        let main = cursors.iter().position(|(_, main)| *main).unwrap();
+        let (main, _) = cursors.remove(main);
+        self.other_cursors = cursors.into_iter().map(|(cursor, _)| cursor).collect();
+        self.use_cursor(main);
+    }
+
+    /// Leaves a cursor where the main one is and moves the main one up or
+    /// down a line, `count` times.
+    fn add_cursor_vertically(&mut self, below: bool, count: usize) {
+        for _ in 0..count {
+            let line = self.current_line();
+            if (below && line + 1 >= self.buffer.get_lines_count()) || (!below && line == 0) {
+                break;
+            }
+            self.other_cursors.push(self.cursor);
+            if below {
+                self.move_cursor_down();
+            } else {
+                self.move_cursor_up();
+            }
+            let pos = self.cursor_position();
+            self.cursor.anchor = pos;
+            self.other_cursors.retain(|cursor| cursor.pos != pos);
+        }
+    }
+
+    /// Leaves a cursor where the main one is and moves the main one to the
+    /// same place in the next match of the word under it that has no
+    /// cursor yet.
+    fn add_cursor_at_next_match(&mut self) {
+        let pos = self.cursor_position();
+        let word = match motion::keyword_under(&self.buffer, pos) {
+            Some(word) => word,
+            None => {
+                self.actions
+                    .push(Action::ShowMessage("No word under cursor".to_owned()));
+                return;
+            }
+        };
+        let text = self.buffer.slice(word);
+        let offset = pos.col - word.start.col;
+        let mut from = word.start;
+        while let Some(found) = motion::next_word_match(&self.buffer, &text, from) {
+            if found == word.start {
+                break;
+            }
+            let target = Position::new(found.line, found.col + offset);
+            if !self.other_cursors.iter().any(|cursor| cursor.pos == target) {
+                self.other_cursors.push(self.cursor);
+                self.set_cursor_position(target);
+                self.cursor.anchor = target;
+                return;
+            }
+            from = found;
+        }
+        self.actions
+            .push(Action::ShowMessage(format!("No more matches for {}", text)));
+    }
+
+    /// `/` or `?`: opens the command line for a search, which moves the
+    /// cursor to the first match as it's typed.
+    fn start_search(&mut self, direction: Direction) {
+        self.incsearch = Some(Incsearch {
+            direction,
+            origin: self.cursor_position(),
+            search: None,
+        });
+        self.actions.push(Action::OpenCmdline(direction.prompt()));
+    }
+
+    /// Moves the cursor to where the search typed so far first matches, or
+    /// back where it was if it doesn't.
+    fn update_search(&mut self, text: &str) {
+        let incsearch = match &mut self.incsearch {
+            Some(incsearch) => incsearch,
+            None => return,
+        };
+        let origin = incsearch.origin;
+        incsearch.search = match text {
+            "" => None,
+            text => Search::new(text, incsearch.direction).ok(),
+        };
+        let found = self
+            .incsearch
+            .as_ref()
+            .and_then(|incsearch| incsearch.search.as_ref())
+            .and_then(|search| search.find(&self.buffer, origin, search.direction));
+        match found {
+            Some((found, _)) => {
+                self.current_match = Some(found);
+                self.set_cursor_position(found.start);
+            }
+            None => {
+                self.current_match = None;
+                self.set_cursor_position(origin);
+            }
+        }
+    }
+
+    /// Enter on a search: searches for `text` from where the cursor was,
+    /// or again for the last search if it's empty.
+    fn finish_search(&mut self, text: &str) {
+        let incsearch = match self.incsearch.take() {
+            Some(incsearch) => incsearch,
+            None => return,
+        };
+        self.current_match = None;
+        self.set_cursor_position(incsearch.origin);
+        if !text.is_empty() {
+            match Search::new(text, incsearch.direction) {
+                Ok(search) => self.search = Some(search),
+                Err(err) => {
+                    self.actions.push(Action::ShowError(err.to_string()));
+                    return;
+                }
+            }
+        } else if let Some(last) = &mut self.search {
+            last.direction = incsearch.direction;
         }
+        self.search_next(false, 1);
     }
 
+    /// Escape on a search: puts the cursor back.
+    fn cancel_search(&mut self) {
+        if let Some(incsearch) = self.incsearch.take() {
+            self.current_match = None;
+            self.set_cursor_position(incsearch.origin);
+        }
+    }
+
+    /// `n`, or `N` when `reverse`: jumps to the `count`th next match of the
+    /// last search, the way it went or the other way.
+    fn search_next(&mut self, reverse: bool, count: usize) {
+        if let Err(err) = self.find_next(reverse, count) {
+            self.actions.push(Action::ShowError(err.to_string()));
+        }
+    }
+
+    fn find_next(&mut self, reverse: bool, count: usize) -> Result<(), ExError> {
+        let search = self.search.clone().ok_or(ExError::NoPreviousPattern)?;
+        let direction = if reverse {
+            search.direction.reverse()
+        } else {
+            search.direction
+        };
+        let mut from = self.cursor_position();
+        let mut wrapped = false;
+        for _ in 0..count {
+            let (found, wrap) = search
+                .find(&self.buffer, from, direction)
+                .ok_or_else(|| ExError::PatternNotFound(search.text.clone()))?;
+            from = found.start;
+            wrapped |= wrap;
+        }
+        self.set_cursor_position(from);
+        self.hlsearch = true;
+        let message = match (wrapped, direction) {
+            (false, _) => search.describe(),
+            (true, Direction::Forward) => "Search hit BOTTOM, continuing at TOP".to_owned(),
+            (true, Direction::Backward) => "Search hit TOP, continuing at BOTTOM".to_owned(),
+        };
+        self.actions.push(Action::ShowMessage(message));
+        Ok(())
+    }
+
+    /// `*` or `#`: searches for the word under the cursor as a whole word.
+    fn search_word(&mut self, direction: Direction, count: usize) {
+        let word = match motion::keyword_under(&self.buffer, self.cursor_position()) {
+            Some(word) => word,
+            None => {
+                self.actions
+                    .push(Action::ShowMessage("No word under cursor".to_owned()));
+                return;
+            }
+        };
+        let text = self.buffer.slice(word);
+        self.search = Some(Search::word(&text, direction));
+        self.set_cursor_position(word.start);
+        self.search_next(false, count);
+    }
+
+    /// Shows the next (or previous) buffer in the list.
+    fn cycle_buffers(&mut self, forward: bool) {
+        if self.hidden_buffers.is_empty() {
+            return;
+        }
+        let next = if forward {
+            self.hidden_buffers.remove(0)
+        } else {
+            self.hidden_buffers.pop().unwrap()
+        };
+        let previous = std::mem::replace(&mut self.buffer, next);
+        if forward {
+            self.hidden_buffers.push(previous);
+        } else {
+            self.hidden_buffers.insert(0, previous);
+        }
+        self.goto_line(1);
+    }
+
+    /// Scrolls the view down by `step` rows, taking the cursor along by as
+    /// many lines as went off the top.
     fn scroll_down(&mut self, step: usize) {
-        if self.offset_y + self.cursor.row as usize + step < self.buffer.get_lines_count() {
-            self.offset_y += step;
+        let lines_count = self.buffer.get_lines_count();
+        let mut lines = 0;
+        let mut rows = 0;
+        while rows < step && self.scroll.top + lines < lines_count {
+            rows += self.line_height(self.scroll.top + lines);
+            lines += 1;
+        }
+        if self.current_line() + lines < lines_count {
+            self.scroll.top += lines;
+            self.cursor.pos.line += lines;
+            self.clamp_cursor();
         }
     }
 
     fn scroll_up(&mut self, step: usize) {
-        if self.offset_y as i32 - step as i32 >= 0 {
-            self.offset_y -= step;
+        let mut lines = 0;
+        let mut rows = 0;
+        while rows < step && lines < self.scroll.top {
+            lines += 1;
+            rows += self.line_height(self.scroll.top - lines);
+        }
+        if rows >= step {
+            self.scroll.top -= lines;
+            self.cursor.pos.line -= lines;
+            self.clamp_cursor();
         }
     }
 
     fn move_cursor_down(&mut self) {
-        if self.cursor.row as usize + 1 > self.viewport_rows - 1 {
-            self.scroll_down(1);
+        if self.current_line() + 1 < self.buffer.get_lines_count() {
+            self.move_to_line(self.current_line() + 1);
+        }
+    }
+
+    fn move_cursor_up(&mut self) {
+        if self.current_line() > 0 {
+            self.move_to_line(self.current_line() - 1);
+        }
+    }
+
+    /// The screen column `pos` is drawn at, counting from the start of its
+    /// line.
+    fn screen_col(&self, pos: Position) -> usize {
+        grapheme::display_col(&self.buffer.line_text(pos.line), pos.col, self.tabstop())
+    }
+
+    /// How many screen columns the grapheme at `pos` takes.
+    fn screen_width_at(&self, pos: Position) -> usize {
+        grapheme::width_at(&self.buffer.line_text(pos.line), pos.col, self.tabstop())
+    }
+
+    /// How many screen columns `line` takes.
+    fn line_width(&self, line: usize) -> usize {
+        grapheme::display_width(&self.buffer.line_text(line), self.tabstop())
+    }
+
+    /// The column of the grapheme drawn over screen column `x` of `line`,
+    /// or the end of the line if it is shorter than that.
+    fn col_at_screen(&self, line: usize, x: usize) -> usize {
+        grapheme::col_at_display(&self.buffer.line_text(line), x, self.tabstop())
+    }
+
+    fn tabstop(&self) -> usize {
+        self.buffer.options().tabstop
+    }
+
+    /// `zh` and `zl`: scrolls the view `step` columns left, or right, when
+    /// lines don't wrap. The cursor is moved along if it would go off
+    /// screen.
+    fn scroll_sideways(&mut self, step: usize, right: bool) {
+        if self.scroll.wrap {
+            return;
+        }
+        self.scroll.left = if right {
+            self.scroll.left + step
         } else {
-            self.cursor.row += 1;
+            self.scroll.left.saturating_sub(step)
+        };
+        let margin = self.scroll.side_margin();
+        let x = self.screen_col(self.cursor.pos);
+        let width = self.screen_width_at(self.cursor.pos);
+        let right_edge = self.scroll.left + self.scroll.columns;
+        let target_x = if x < self.scroll.left + margin {
+            self.scroll.left + margin
+        } else if x + width + margin > right_edge {
+            right_edge.saturating_sub(margin + 1)
+        } else {
+            return;
+        };
+        let line = self.current_line();
+        self.set_cursor_position(Position::new(line, self.col_at_screen(line, target_x)));
+    }
+
+    /// `zs`: scrolls sideways so the cursor is at the left edge of the
+    /// screen, or at the right edge for `ze`.
+    fn scroll_to_cursor(&mut self, end: bool) {
+        if self.scroll.wrap {
+            return;
         }
-        if self.last_column != -1 {
-            self.cursor.col = self.last_column;
+        let margin = self.scroll.side_margin();
+        let x = self.screen_col(self.cursor.pos);
+        self.scroll.left = if end {
+            (x + self.screen_width_at(self.cursor.pos) + margin).saturating_sub(self.scroll.columns)
+        } else {
+            x.saturating_sub(margin)
+        };
+    }
+
+    /// What the gutter's decorations see of the view.
+    fn gutter_context(&self) -> gutter::Context<'_> {
+        gutter::Context {
+            buffer: &self.buffer,
+            current_line: self.current_line(),
+            number: self.number,
+            relativenumber: self.relativenumber,
         }
-        self.last_column = -1;
-        self.move_to_eol(true);
     }
 
-    fn move_cursor_up(&mut self) {
-        if self.cursor.row > 0 {
-            self.cursor.row -= 1;
+    /// The screen column of `row`'s line that is at the left edge of the
+    /// screen.
+    fn row_left(&self, row: &Row) -> usize {
+        if self.scroll.wrap {
+            row.x
         } else {
-            self.scroll_up(1);
+            self.scroll.left
         }
-        if self.last_column != -1 {
-            self.cursor.col = self.last_column;
+    }
+
+    /// The rows `line` takes on screen, which is one unless it wraps.
+    fn line_rows(&self, line: usize) -> Vec<Row> {
+        let text = self.buffer.line_text(line);
+        let line_len = text.chars().count();
+        if !self.scroll.wrap || self.scroll.columns == 0 {
+            return vec![Row {
+                line,
+                start: 0,
+                end: line_len,
+                x: 0,
+                last: true,
+            }];
         }
-        self.last_column = -1;
-        self.move_to_eol(true);
+        let tabstop = self.tabstop();
+        let starts = grapheme::wrap(&text, self.scroll.wrap_width(), tabstop);
+        let ends = starts
+            .iter()
+            .skip(1)
+            .cloned()
+            .chain(std::iter::once(line_len));
+        starts
+            .iter()
+            .zip(ends)
+            .enumerate()
+            .map(|(i, (&start, end))| Row {
+                line,
+                start,
+                end,
+                x: grapheme::display_col(&text, start, tabstop),
+                last: i + 1 == starts.len(),
+            })
+            .collect()
     }
 
-    fn move_cursor_left(&mut self) {
-        if self.cursor.col > 0 {
-            self.cursor.col -= 1;
+    fn line_height(&self, line: usize) -> usize {
+        self.line_rows(line).len()
+    }
+
+    /// The rows on screen, from the top line down.
+    fn screen_rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        let mut line = self.scroll.top;
+        while rows.len() < self.scroll.rows && line < self.buffer.get_lines_count() {
+            rows.extend(self.line_rows(line));
+            line += 1;
         }
-        self.last_column = -1;
+        rows.truncate(self.scroll.rows);
+        rows
+    }
+
+    /// Where `count` rows on screen below `pos` is, or above it for `up`,
+    /// going for the screen column `goal_col` as `j` and `k` do. Also gives
+    /// the screen column it was going for on the last row.
+    fn row_target(
+        &self,
+        pos: Position,
+        goal_col: Option<usize>,
+        count: usize,
+        up: bool,
+    ) -> (Position, usize) {
+        let mut rows = self.line_rows(pos.line);
+        let mut index = rows.iter().position(|row| row.contains(pos)).unwrap_or(0);
+        let offset = goal_col
+            .unwrap_or_else(|| self.screen_col(pos))
+            .saturating_sub(rows[index].x);
+        for _ in 0..count {
+            let line = rows[index].line;
+            if up && index > 0 {
+                index -= 1;
+            } else if up && line > 0 {
+                rows = self.line_rows(line - 1);
+                index = rows.len() - 1;
+            } else if !up && index + 1 < rows.len() {
+                index += 1;
+            } else if !up && line + 1 < self.buffer.get_lines_count() {
+                rows = self.line_rows(line + 1);
+                index = 0;
+            } else {
+                break;
+            }
+        }
+        let row = rows[index];
+        let text = self.buffer.line_text(row.line);
+        let goal_col = row.x + offset;
+        let mut col = grapheme::col_at_display(&text, goal_col, self.tabstop());
+        if !row.last {
+            col = col.min(grapheme::prev_boundary(&text, row.end));
+        }
+        (Position::new(row.line, col), goal_col)
+    }
+
+    /// `gj` and `gk`: moves the cursor `count` rows on screen down or up.
+    fn move_by_rows(&mut self, count: usize, up: bool) {
+        let (pos, goal_col) = self.row_target(self.cursor.pos, self.cursor.goal_col, count, up);
+        self.cursor.pos = pos;
+        self.clamp_cursor();
+        self.cursor.goal_col = if self.screen_col(self.cursor.pos) == goal_col {
+            None
+        } else {
+            Some(goal_col)
+        };
+    }
+
+    /// Moves the cursor to `line`, keeping it in the same screen column.
+    /// After lines too short for that (or with a wide char in the way) it
+    /// goes back to the column it was aiming for.
+    fn move_to_line(&mut self, line: usize) {
+        let goal_col = self
+            .cursor
+            .goal_col
+            .unwrap_or_else(|| self.screen_col(self.cursor.pos));
+        self.cursor.pos.line = line;
+        let text = self.buffer.line_text(line);
+        let col = grapheme::col_at_display(&text, goal_col, self.tabstop());
+        self.cursor.pos.col = grapheme::floor_boundary(&text, col.min(self.max_column()));
+        self.cursor.goal_col = if self.screen_col(self.cursor.pos) == goal_col {
+            None
+        } else {
+            Some(goal_col)
+        };
+    }
+
+    fn move_cursor_left(&mut self) {
+        let text = self.buffer.line_text(self.current_line());
+        self.cursor.pos.col = grapheme::prev_boundary(&text, self.cursor.pos.col);
+        self.cursor.goal_col = None;
     }
 
     fn move_cursor_right(&mut self) {
-        let current_line = self
-            .buffer
-            .get_line_at(self.offset_y + self.cursor.row as usize);
-        if current_line.len() - 1 > self.cursor.col as usize {
-            self.last_column = self.cursor.col;
-            self.cursor.col += 1;
+        let text = self.buffer.line_text(self.current_line());
+        let next = grapheme::next_boundary(&text, self.cursor.pos.col);
+        if next <= self.max_column() {
+            self.cursor.pos.col = next;
         }
-        self.last_column = -1;
+        self.cursor.goal_col = None;
     }
 
     fn move_to_bol(&mut self) {
-        self.cursor.col = 0;
-        self.last_column = -1;
+        self.cursor.pos.col = 0;
+        self.cursor.goal_col = None;
+    }
+
+    fn move_to_eol(&mut self) {
+        self.cursor.pos.col = self.max_column();
+        self.cursor.goal_col = None;
+    }
+
+    /// Pulls the cursor back onto its line if it is past the end, keeping
+    /// the screen column it was on as the one to aim for, and onto the
+    /// start of the grapheme it is in.
+    fn clamp_cursor(&mut self) {
+        let max_column = self.max_column();
+        if self.cursor.pos.col > max_column {
+            self.cursor.goal_col = Some(self.screen_col(self.cursor.pos));
+            self.cursor.pos.col = max_column;
+        }
+        let text = self.buffer.line_text(self.current_line());
+        self.cursor.pos.col = grapheme::floor_boundary(&text, self.cursor.pos.col);
+    }
+
+    /// The rightmost column the cursor can be on in the current line: the
+    /// last grapheme, or in insert mode just past it.
+    fn max_column(&self) -> usize {
+        let line = self.current_line();
+        let line_len = self.buffer.line_len(line);
+        match self.mode {
+            Mode::Insert => line_len,
+            Mode::Normal | Mode::Visual(_) => {
+                grapheme::prev_boundary(&self.buffer.line_text(line), line_len)
+            }
+        }
+    }
+
+    /// Whether the buffer can be edited, telling the user why not if it
+    /// can't.
+    fn check_modifiable(&mut self) -> bool {
+        if self.buffer.is_read_only() {
+            self.actions
+                .push(Action::ShowMessage("Buffer is read-only".to_owned()));
+            return false;
+        }
+        true
+    }
+
+    fn enter_insert_mode(&mut self) {
+        self.mode = Mode::Insert;
+        self.buffer.begin_undo_group(self.cursor_position());
+        self.cursor.goal_col = None;
+    }
+
+    fn leave_insert_mode(&mut self) {
+        self.mode = Mode::Normal;
+        if let Some(block) = self.block_insert.take() {
+            self.finish_block_insert(block);
+        }
+        self.buffer.end_undo_group();
+        self.for_each_cursor(Self::move_cursor_left);
+    }
+
+    /// Puts what was typed on the first line of a block insert on the rest
+    /// of its lines.
+    fn finish_block_insert(&mut self, block: BlockInsert) {
+        let line_len = self.buffer.line_len(block.first);
+        if self.current_line() != block.first || line_len <= block.line_len {
+            return;
+        }
+        let start = self.col_at_screen(block.first, block.col);
+        let text = self.buffer.slice(Range::new(
+            Position::new(block.first, start),
+            Position::new(block.first, start + line_len - block.line_len),
+        ));
+        for line in block.first + 1..=block.last {
+            let width = self.line_width(line);
+            if width >= block.col {
+                let col = self.col_at_screen(line, block.col);
+                self.buffer.insert(Position::new(line, col), &text);
+            } else if block.pad {
+                let padding = " ".repeat(block.col - width);
+                let eol = Position::new(line, self.buffer.line_len(line));
+                self.buffer.insert(eol, &(padding + &text));
+            }
+        }
+    }
+
+    /// Starts visual mode of `kind` with the anchors on the cursors. From
+    /// another visual mode it switches kind and keeps the anchors, and from
+    /// the same one it goes back to normal mode.
+    fn toggle_visual(&mut self, kind: VisualKind) {
+        match self.mode {
+            Mode::Visual(current) if current == kind => self.leave_visual(),
+            Mode::Visual(_) => self.mode = Mode::Visual(kind),
+            _ => {
+                self.cursor.anchor = self.cursor_position();
+                for cursor in self.other_cursors.iter_mut() {
+                    cursor.anchor = cursor.pos;
+                }
+                self.mode = Mode::Visual(kind);
+            }
+        }
+        // Blocks from several cursors can't be lined up, so a block takes
+        // the main cursor only.
+        if self.mode == Mode::Visual(VisualKind::Block) {
+            self.other_cursors.clear();
+        }
+    }
+
+    fn leave_visual(&mut self) {
+        self.mode = Mode::Normal;
+    }
+
+    fn swap_anchor(&mut self) {
+        let cursor = self.cursor_position();
+        self.set_cursor_position(self.cursor.anchor);
+        self.cursor.anchor = cursor;
     }
 
-    fn move_to_eol(&mut self, try_first: bool) {
-        let current_line = self
+    /// Selects `object` around the cursor, switching to the visual mode
+    /// that fits it.
+    fn select_object(&mut self, object: TextObject) {
+        match object.select(&self.buffer, self.cursor_position()) {
+            Some(Span::Chars(range)) if !range.is_empty() => {
+                self.mode = Mode::Visual(VisualKind::Char);
+                self.cursor.anchor = range.start;
+                let end = self.buffer.pos_to_char(range.end) - 1;
+                self.set_cursor_position(self.buffer.char_to_pos(end));
+            }
+            Some(Span::Lines(first, last)) => {
+                self.mode = Mode::Visual(VisualKind::Line);
+                self.cursor.anchor = Position::new(first, 0);
+                self.set_cursor_position(Position::new(last, 0));
+            }
+            _ => (),
+        }
+    }
+
+    /// The text a visual selection of `kind` between `anchor` and `cursor`
+    /// covers, which includes the char under the cursor.
+    fn selection(&self, kind: VisualKind, anchor: Position, cursor: Position) -> Span {
+        let range = Range::new(anchor, cursor);
+        match kind {
+            VisualKind::Line => Span::Lines(range.start.line, range.end.line),
+            VisualKind::Block => {
+                // The corners are on screen columns, so the block stays
+                // square across tabs and wide chars.
+                let edges = |pos: Position| {
+                    let x = self.screen_col(pos);
+                    (x, x + self.screen_width_at(pos))
+                };
+                let (anchor_left, anchor_right) = edges(anchor);
+                let (cursor_left, cursor_right) = edges(cursor);
+                Span::Block(Range {
+                    start: Position::new(range.start.line, anchor_left.min(cursor_left)),
+                    end: Position::new(range.end.line, anchor_right.max(cursor_right)),
+                })
+            }
+            VisualKind::Char => {
+                // On an empty line the line break is selected instead.
+                let end = if range.end.col < self.buffer.line_len(range.end.line) {
+                    let text = self.buffer.line_text(range.end.line);
+                    Position::new(
+                        range.end.line,
+                        grapheme::next_boundary(&text, range.end.col),
+                    )
+                } else if range.end.line + 1 < self.buffer.get_lines_count() {
+                    Position::new(range.end.line + 1, 0)
+                } else {
+                    range.end
+                };
+                Span::Chars(Range::new(range.start, end))
+            }
+        }
+    }
+
+    /// A row of `█` over the screen columns `span` covers on each of
+    /// `rows`.
+    fn selection_highlight(&self, span: Span, rows: &[Row]) -> String {
+        let rows: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let (start, end) = self.span_on_row(span, row);
+                " ".repeat(start) + &"█".repeat(end.saturating_sub(start))
+            })
+            .collect();
+        rows.join("\n")
+    }
+
+    /// Like `selection_highlight`, over every one of `ranges`.
+    fn ranges_highlight(&self, ranges: &[Range], rows: &[Row]) -> String {
+        let rows: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let mut cells = vec![' '; self.scroll.columns];
+                for range in ranges {
+                    let (start, end) = self.span_on_row(Span::Chars(*range), row);
+                    for cell in cells.iter_mut().take(end).skip(start) {
+                        *cell = '█';
+                    }
+                }
+                cells.into_iter().collect::<String>().trim_end().to_owned()
+            })
+            .collect();
+        rows.join("\n")
+    }
+
+    /// A highlight over the matches on `rows` of the search being typed, or
+    /// of the last one unless hidden with `:noh`.
+    fn search_highlight(&self, rows: &[Row]) -> Option<String> {
+        let search = match &self.incsearch {
+            Some(incsearch) => incsearch.search.as_ref()?,
+            None if self.hlsearch => self.search.as_ref()?,
+            None => return None,
+        };
+        let matches = search.matches_in(&self.buffer, rows.first()?.line, rows.last()?.line);
+        Some(self.ranges_highlight(&matches, rows))
+    }
+
+    /// The screen columns `span` covers on `row`, as far as they are on
+    /// screen, counted from its left edge.
+    fn span_on_row(&self, span: Span, row: &Row) -> (usize, usize) {
+        let line = row.line;
+        let width = self.line_width(line);
+        let (start, end) = match span {
+            Span::Chars(range) if range.start.line <= line && line <= range.end.line => {
+                let start = if line == range.start.line {
+                    self.screen_col(range.start)
+                } else {
+                    0
+                };
+                let end = if line == range.end.line {
+                    self.screen_col(range.end)
+                } else {
+                    width + 1
+                };
+                (start, end)
+            }
+            Span::Lines(first, last) if first <= line && line <= last => (0, width.max(1)),
+            Span::Block(range) if range.start.line <= line && line <= range.end.line => {
+                (range.start.col.min(width), range.end.col.min(width))
+            }
+            _ => (0, 0),
+        };
+        // Just the part on this row, and on screen.
+        let row_end = if row.last {
+            usize::MAX
+        } else {
+            self.screen_col(Position::new(line, row.end))
+        };
+        let left = self.row_left(row);
+        let start = start.max(row.x).max(left);
+        let end = end.min(row_end).min(left + self.scroll.columns);
+        (start - left, end.saturating_sub(left))
+    }
+
+    fn operate_selection(&mut self, operator: Operator, register: Option<char>) {
+        if let Mode::Visual(kind) = self.mode {
+            self.mode = Mode::Normal;
+            self.for_each_cursor(|editor| {
+                let span = editor.selection(kind, editor.cursor.anchor, editor.cursor_position());
+                editor.operate(operator, span, register);
+            });
+        }
+    }
+
+    /// Starts inserting before the block on its first line, or after it for
+    /// `A`, to be repeated on the other lines.
+    fn block_insert(&mut self, append: bool) {
+        let range = match self.selection(
+            VisualKind::Block,
+            self.cursor.anchor,
+            self.cursor_position(),
+        ) {
+            Span::Block(range) => range,
+            _ => return,
+        };
+        self.mode = Mode::Normal;
+        if !self.check_modifiable() {
+            return;
+        }
+        let first = range.start.line;
+        let width = self.line_width(first);
+        let col = if append {
+            range.end.col
+        } else {
+            range.start.col.min(width)
+        };
+        self.buffer.begin_undo_group(self.cursor_position());
+        if width < col {
+            let eol = Position::new(first, self.buffer.line_len(first));
+            self.buffer.insert(eol, &" ".repeat(col - width));
+        }
+        self.block_insert = Some(BlockInsert {
+            first,
+            last: range.end.line,
+            col,
+            line_len: self.buffer.line_len(first),
+            pad: append,
+        });
+        self.enter_insert_mode();
+        self.set_cursor_position(Position::new(first, self.col_at_screen(first, col)));
+    }
+
+    /// Ends the current insert-mode undo step and starts a new one, so
+    /// moving around while inserting splits the typing into separate steps.
+    fn break_undo_group(&mut self) {
+        self.buffer.end_undo_group();
+        self.buffer.begin_undo_group(self.cursor_position());
+    }
+
+    fn undo(&mut self) {
+        self.other_cursors.clear();
+        match self.buffer.undo() {
+            Some(pos) => self.set_cursor_position(pos),
+            None => self
+                .actions
+                .push(Action::ShowMessage("Already at oldest change".to_owned())),
+        }
+    }
+
+    fn redo(&mut self) {
+        self.other_cursors.clear();
+        match self.buffer.redo() {
+            Some(pos) => self.set_cursor_position(pos),
+            None => self
+                .actions
+                .push(Action::ShowMessage("Already at newest change".to_owned())),
+        }
+    }
+
+    /// Goes `steps` changes back or forward in time, across undo branches.
+    fn travel_history(&mut self, steps: isize) {
+        self.other_cursors.clear();
+        match self.buffer.travel(steps) {
+            Some(pos) => self.set_cursor_position(pos),
+            None if steps < 0 => self
+                .actions
+                .push(Action::ShowMessage("Already at oldest change".to_owned())),
+            None => self
+                .actions
+                .push(Action::ShowMessage("Already at newest change".to_owned())),
+        }
+    }
+
+    fn insert_before_cursor(&mut self) {
+        if self.check_modifiable() {
+            self.enter_insert_mode();
+        }
+    }
+
+    fn append_after_cursor(&mut self) {
+        if !self.check_modifiable() {
+            return;
+        }
+        let text = self.buffer.line_text(self.current_line());
+        self.cursor.pos.col = grapheme::next_boundary(&text, self.cursor.pos.col);
+        self.enter_insert_mode();
+    }
+
+    fn append_at_eol(&mut self) {
+        if !self.check_modifiable() {
+            return;
+        }
+        self.cursor.pos.col = self.buffer.line_len(self.current_line());
+        self.enter_insert_mode();
+    }
+
+    fn insert_at_bol(&mut self) {
+        if !self.check_modifiable() {
+            return;
+        }
+        let line = self.buffer.get_line_at(self.current_line());
+        self.cursor.pos.col = line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        self.enter_insert_mode();
+    }
+
+    fn open_line_below(&mut self) {
+        if !self.check_modifiable() {
+            return;
+        }
+        self.buffer.begin_undo_group(self.cursor_position());
+        let line = self.current_line();
+        self.buffer
+            .split_line(Position::new(line, self.buffer.line_len(line)));
+        self.move_cursor_down();
+        self.cursor.pos.col = 0;
+        self.enter_insert_mode();
+    }
+
+    fn open_line_above(&mut self) {
+        if !self.check_modifiable() {
+            return;
+        }
+        self.buffer.begin_undo_group(self.cursor_position());
+        self.buffer
+            .split_line(Position::new(self.current_line(), 0));
+        self.cursor.pos.col = 0;
+        self.enter_insert_mode();
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let change = self
             .buffer
-            .get_line_at(self.offset_y + self.cursor.row as usize);
-        if try_first {
-            if self.cursor.col as usize > current_line.len() {
-                self.last_column = self.cursor.col;
-                self.cursor.col = current_line.len() as i32 - 1;
+            .insert(self.cursor_position(), c.encode_utf8(&mut [0; 4]));
+        self.cursor.pos.col = change.new_end.col;
+    }
+
+    /// Puts in a tab, or with `expandtab` the spaces up to the next tab
+    /// stop.
+    fn insert_tab(&mut self) {
+        let options = *self.buffer.options();
+        if options.expandtab {
+            let x = self.screen_col(self.cursor.pos);
+            let spaces = options.tabstop - x % options.tabstop;
+            let change = self
+                .buffer
+                .insert(self.cursor_position(), &" ".repeat(spaces));
+            self.cursor.pos.col = change.new_end.col;
+        } else {
+            self.insert_char('\t');
+        }
+    }
+
+    fn insert_newline(&mut self) {
+        self.buffer.split_line(self.cursor_position());
+        self.move_cursor_down();
+        self.cursor.pos.col = 0;
+        self.cursor.goal_col = None;
+    }
+
+    fn delete_char_before_cursor(&mut self) {
+        let line = self.current_line();
+        if self.cursor.pos.col > 0 {
+            let pos = self.cursor_position();
+            let text = self.buffer.line_text(line);
+            let start = Position::new(line, grapheme::prev_boundary(&text, pos.col));
+            let change = self.buffer.delete(Range::new(start, pos));
+            self.cursor.pos.col = change.start.col;
+        } else if line > 0 {
+            if let Some(change) = self.buffer.join_lines(line - 1, "") {
+                self.move_cursor_up();
+                self.cursor.pos.col = change.start.col;
+                self.cursor.goal_col = None;
             }
+        }
+    }
+
+    fn join_with_next_line(&mut self) {
+        if !self.check_modifiable() {
+            return;
+        }
+        if let Some(change) = self.buffer.join_trimmed(self.current_line()) {
+            self.cursor.pos.col = change.start.col;
+            self.cursor.goal_col = None;
+        }
+    }
+
+    /// Writes the buffer to its file, or to `path`, and says so.
+    fn write(&mut self, path: Option<&str>) -> Result<(), ExError> {
+        let bytes = match path {
+            Some(path) => self.buffer.save_as(std::path::Path::new(path)),
+            None => self.buffer.save(),
+        }
+        .map_err(ExError::Write)?;
+        self.actions.push(Action::ShowMessage(format!(
+            "\"{}\" {}L, {}B written",
+            self.buffer.path().unwrap().display(),
+            self.buffer.get_lines_count(),
+            bytes
+        )));
+        Ok(())
+    }
+
+    fn save(&mut self) {
+        if let Err(err) = self.write(None) {
+            self.actions.push(Action::ShowError(err.to_string()));
+        }
+    }
+
+    /// The commands the command line runs. Each is looked up by its name
+    /// cut short as far as the part in brackets, in this order.
+    fn ex_commands() -> Commands<Self> {
+        let mut commands = Commands::new();
+        commands.register("w[rite]", false, Completion::Files, Self::ex_write);
+        commands.register("wq", false, Completion::Files, Self::ex_write_quit);
+        commands.register("x[it]", false, Completion::Files, Self::ex_write_quit);
+        commands.register("q[uit]", false, Completion::Nothing, Self::ex_quit);
+        commands.register("e[dit]", false, Completion::Files, Self::ex_edit);
+        commands.register("d[elete]", true, Completion::Nothing, Self::ex_delete);
+        commands.register("y[ank]", true, Completion::Nothing, Self::ex_yank);
+        commands.register("bn[ext]", false, Completion::Nothing, Self::ex_next_buffer);
+        commands.register(
+            "bp[revious]",
+            false,
+            Completion::Nothing,
+            Self::ex_previous_buffer,
+        );
+        commands.register("u[ndo]", false, Completion::Nothing, Self::ex_undo);
+        commands.register("red[o]", false, Completion::Nothing, Self::ex_redo);
+        commands.register("earlier", false, Completion::Nothing, Self::ex_earlier);
+        commands.register("later", false, Completion::Nothing, Self::ex_later);
+        commands.register(
+            "s[ubstitute]",
+            true,
+            Completion::Nothing,
+            Self::ex_substitute,
+        );
+        commands.register("se[t]", false, Completion::Options, Self::ex_set);
+        commands.register(
+            "noh[lsearch]",
+            false,
+            Completion::Nothing,
+            Self::ex_nohlsearch,
+        );
+        commands.bind("write", "C-s");
+        commands.bind("delete", "dd");
+        commands.bind("yank", "yy");
+        commands.bind("undo", "u");
+        commands.bind("redo", "C-r");
+        commands.bind("earlier", "g-");
+        commands.bind("later", "g+");
+        commands
+    }
+
+    fn run_command(&mut self, text: &str) {
+        if let Err(err) = self.run_ex_command(text) {
+            self.actions.push(Action::ShowError(err.to_string()));
+        }
+    }
+
+    /// Runs a line typed into the command line. A range on its own goes to
+    /// the last line in it.
+    fn run_ex_command(&mut self, text: &str) -> Result<(), ExError> {
+        let last_line = self.buffer.get_lines_count() - 1;
+        let command = ex::parse(text, self.current_line(), last_line)?;
+        if command.name.is_empty() && !command.bang {
+            command.no_argument()?;
+            if let Some(range) = command.range {
+                self.set_cursor_position(motion::first_non_blank(&self.buffer, range.end));
+            }
+            return Ok(());
+        }
+        let definition = self
+            .ex_commands
+            .find(command.name)
+            .ok_or_else(|| ExError::NotACommand(text.trim().to_owned()))?;
+        if command.range.is_some() && !definition.ranged {
+            return Err(ExError::NoRangeAllowed);
+        }
+        let run = definition.run;
+        run(self, &command)
+    }
+
+    /// `:w [file]`
+    fn ex_write(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        self.write(command.argument)
+    }
+
+    /// `:wq [file]`, and `:x [file]`, which only writes if there are
+    /// changes.
+    fn ex_write_quit(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        if command.name == "wq" || self.buffer.is_dirty() || command.argument.is_some() {
+            self.write(command.argument)?;
+        }
+        self.ex_quit(&ExCommand {
+            argument: None,
+            ..*command
+        })
+    }
+
+    /// `:q`, unless a buffer has changes. `:q!` quits anyway.
+    fn ex_quit(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        command.no_argument()?;
+        if !command.bang {
+            if self.buffer.is_dirty() {
+                return Err(ExError::NoWriteSinceLastChange(self.buffer_name()));
+            }
+            if let Some(buffer) = self.hidden_buffers.iter().find(|b| b.is_dirty()) {
+                let name = buffer
+                    .path()
+                    .map_or("[No Name]".to_owned(), |path| path.display().to_string());
+                return Err(ExError::NoWriteSinceLastChange(name));
+            }
+        }
+        self.actions.push(Action::Quit);
+        Ok(())
+    }
+
+    /// `:e file` shows `file`, opening it unless it already is. `:e` on its
+    /// own loads the current file again, and `:e!` does even if that throws
+    /// changes away.
+    fn ex_edit(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        let file = match command.argument {
+            Some(file) => file,
+            None => {
+                if self.buffer.is_dirty() && !command.bang {
+                    return Err(ExError::NoWriteSinceLastChange(self.buffer_name()));
+                }
+                let path = self.buffer.path().ok_or(ExError::NoFileName)?;
+                let file = path.display().to_string();
+                self.buffer = Buffer::open(&file).map_err(ExError::Open)?;
+                self.other_cursors.clear();
+                self.use_cursor(self.cursor);
+                return Ok(());
+            }
+        };
+        self.open_file(file)
+    }
+
+    /// Shows `file`, opening it unless it's already in a buffer.
+    fn open_file(&mut self, file: &str) -> Result<(), ExError> {
+        let path = std::path::Path::new(file);
+        if self.buffer.has_path(path) {
+            return Ok(());
+        }
+        let buffer = match self
+            .hidden_buffers
+            .iter()
+            .position(|buffer| buffer.has_path(path))
+        {
+            Some(index) => self.hidden_buffers.remove(index),
+            None => {
+                let buffer = Buffer::open(file).map_err(ExError::Open)?;
+                if buffer.is_read_only() {
+                    self.actions.push(read_only_notice(file));
+                }
+                buffer
+            }
+        };
+        let previous = std::mem::replace(&mut self.buffer, buffer);
+        self.hidden_buffers.insert(0, previous);
+        self.goto_line(1);
+        Ok(())
+    }
+
+    /// `:noh[lsearch]`, hiding the search highlight until the next search.
+    fn ex_nohlsearch(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        command.no_argument()?;
+        self.hlsearch = false;
+        Ok(())
+    }
+
+    /// `:[range]s/pattern/replacement/[flags]`, or `:[range]s` to do the
+    /// last one again without its flags. An empty pattern is the last
+    /// search. All the replacements undo as one step.
+    fn ex_substitute(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        let mut substitute = match command.argument {
+            Some(argument) => Substitute::parse(argument)?,
+            None => Substitute {
+                global: false,
+                confirm: false,
+                ignore_case: None,
+                ..self
+                    .last_substitute
+                    .clone()
+                    .ok_or(ExError::NoPreviousSubstitute)?
+            },
+        };
+        if substitute.pattern.is_empty() {
+            let search = self.search.as_ref().ok_or(ExError::NoPreviousPattern)?;
+            substitute.pattern = search.text.clone();
+        }
+        let regex = search::compile(&substitute.pattern, substitute.ignore_case)?;
+        if !self.check_modifiable() {
+            return Ok(());
+        }
+        // Like vim, the pattern becomes the last search.
+        let direction = self
+            .search
+            .as_ref()
+            .map_or(Direction::Forward, |search| search.direction);
+        self.search = Some(Search::new(&substitute.pattern, direction)?);
+        self.hlsearch = true;
+        let range = command.lines(self.current_line());
+        let substitution = Substitution::new(regex, &substitute, range.start, range.end);
+        self.buffer.begin_undo_group(self.cursor_position());
+        if substitute.confirm {
+            self.confirm_next(substitution);
         } else {
-            self.cursor.col = current_line.len() as i32 - 1;
-            self.last_column = -1;
+            self.substitute_rest(substitution);
+        }
+        self.last_substitute = Some(substitute);
+        Ok(())
+    }
+
+    /// Replaces every match `substitution` has left.
+    fn substitute_rest(&mut self, mut substitution: Substitution) {
+        while let Some((found, text)) = substitution.next_match(&self.buffer) {
+            substitution.replace(&mut self.buffer, found, &text);
+        }
+        self.finish_substitution(substitution);
+    }
+
+    /// Highlights the next match of a `:s` with the confirm flag and asks
+    /// whether to replace it.
+    fn confirm_next(&mut self, mut substitution: Substitution) {
+        match substitution.next_match(&self.buffer) {
+            Some((found, text)) => {
+                self.current_match = Some(found);
+                self.set_cursor_position(found.start);
+                self.actions.push(Action::ShowMessage(format!(
+                    "replace with {} (y/n/a/q/l)?",
+                    text
+                )));
+                self.confirming = Some((substitution, found, text));
+            }
+            None => self.finish_substitution(substitution),
+        }
+    }
+
+    /// Acts on an answer to `confirm_next`: `y` replaces the match, `l`
+    /// replaces it and stops, `n` skips it, `a` replaces it and all the
+    /// rest and `q` stops.
+    fn answer_confirm(&mut self, answer: char) {
+        let (mut substitution, found, text) = match self.confirming.take() {
+            Some(confirming) => confirming,
+            None => return,
+        };
+        match answer {
+            'y' | 'l' | 'a' => substitution.replace(&mut self.buffer, found, &text),
+            'n' => substitution.skip(found),
+            'q' => (),
+            _ => {
+                self.confirming = Some((substitution, found, text));
+                return;
+            }
+        }
+        match answer {
+            'y' | 'n' => self.confirm_next(substitution),
+            'a' => self.substitute_rest(substitution),
+            _ => self.finish_substitution(substitution),
+        }
+    }
+
+    /// Ends the undo step of a `:s`, puts the cursor on the last line it
+    /// changed and says how it went.
+    fn finish_substitution(&mut self, substitution: Substitution) {
+        self.current_match = None;
+        self.buffer.end_undo_group();
+        if let Some(line) = substitution.last_line {
+            self.set_cursor_position(motion::first_non_blank(&self.buffer, line));
+        }
+        if substitution.found == 0 {
+            let pattern = self.search.as_ref().map_or("", |search| &search.text);
+            let err = ExError::PatternNotFound(pattern.to_owned());
+            self.actions.push(Action::ShowError(err.to_string()));
+        } else if substitution.count > 0 {
+            let plural = |n: usize| if n == 1 { "" } else { "s" };
+            self.actions.push(Action::ShowMessage(format!(
+                "{} substitution{} on {} line{}",
+                substitution.count,
+                plural(substitution.count),
+                substitution.lines,
+                plural(substitution.lines)
+            )));
+        }
+    }
+
+    /// `:[range]d [x]`, deleting whole lines into register `x`.
+    fn ex_delete(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        self.ex_operate(Operator::Delete, command)
+    }
+
+    /// `:[range]y [x]`, yanking whole lines into register `x`.
+    fn ex_yank(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        self.ex_operate(Operator::Yank, command)
+    }
+
+    fn ex_operate(&mut self, operator: Operator, command: &ExCommand) -> Result<(), ExError> {
+        let register = match command.argument {
+            Some(name) if name.chars().count() == 1 => name.chars().next(),
+            Some(name) => return Err(ExError::TrailingCharacters(name.to_owned())),
+            None => None,
+        };
+        if operator != Operator::Yank && !self.check_modifiable() {
+            return Ok(());
         }
+        let range = command.lines(self.current_line());
+        self.operate(operator, Span::Lines(range.start, range.end), register);
+        Ok(())
+    }
+
+    fn ex_next_buffer(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        command.no_argument()?;
+        self.cycle_buffers(true);
+        Ok(())
+    }
+
+    fn ex_previous_buffer(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        command.no_argument()?;
+        self.cycle_buffers(false);
+        Ok(())
+    }
+
+    fn ex_undo(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        command.no_argument()?;
+        self.undo();
+        Ok(())
+    }
+
+    fn ex_redo(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        command.no_argument()?;
+        self.redo();
+        Ok(())
+    }
+
+    /// `:earlier N`, going back N changes in time.
+    fn ex_earlier(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        let count = count_argument(command)?;
+        self.travel_history(-count);
+        Ok(())
+    }
+
+    /// `:later N`, going forward N changes in time.
+    fn ex_later(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        let count = count_argument(command)?;
+        self.travel_history(count);
+        Ok(())
+    }
+
+    /// `:set name=value`, `:set name` and `:set noname` for each option
+    /// given, or `:set name?` to show its value. Number options show their
+    /// value when set without one.
+    fn ex_set(&mut self, command: &ExCommand) -> Result<(), ExError> {
+        for option in command.argument.unwrap_or("").split_whitespace() {
+            if let Some(message) = self.set_option(option)? {
+                self.actions.push(Action::ShowMessage(message));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets one option, or gives its value to show.
+    fn set_option(&mut self, option: &str) -> Result<Option<String>, ExError> {
+        let mut parts = option.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let query = name.ends_with('?');
+        let name = name.trim_end_matches('?');
+        let value = parts.next();
+        let options = self.buffer.options_mut();
+        let number = match name {
+            "so" | "scrolloff" => Some(("scrolloff", &mut self.scroll.scrolloff)),
+            "ts" | "tabstop" => Some(("tabstop", &mut options.tabstop)),
+            "sw" | "shiftwidth" => Some(("shiftwidth", &mut options.shiftwidth)),
+            "siso" | "sidescrolloff" => Some(("sidescrolloff", &mut self.scroll.sidescrolloff)),
+            _ => None,
+        };
+        if let Some((name, setting)) = number {
+            match value {
+                None => Ok(Some(format!("{}={}", name, setting))),
+                // A tab has to move to somewhere.
+                Some(value) => match value.parse() {
+                    Ok(0) if name == "tabstop" => Err(ExError::InvalidArgument(option.to_owned())),
+                    Ok(value) => {
+                        *setting = value;
+                        Ok(None)
+                    }
+                    Err(_) => Err(ExError::InvalidArgument(option.to_owned())),
+                },
+            }
+        } else {
+            let (name, flag, on) = match name {
+                "et" | "expandtab" => ("expandtab", &mut options.expandtab, true),
+                "noet" | "noexpandtab" => ("expandtab", &mut options.expandtab, false),
+                "nu" | "number" => ("number", &mut self.number, true),
+                "nonu" | "nonumber" => ("number", &mut self.number, false),
+                "rnu" | "relativenumber" => ("relativenumber", &mut self.relativenumber, true),
+                "nornu" | "norelativenumber" => ("relativenumber", &mut self.relativenumber, false),
+                "wrap" => ("wrap", &mut self.scroll.wrap, true),
+                "nowrap" => ("wrap", &mut self.scroll.wrap, false),
+                _ => return Err(ExError::UnknownOption(name.to_owned())),
+            };
+            if value.is_some() {
+                Err(ExError::InvalidArgument(option.to_owned()))
+            } else if query {
+                Ok(Some(format!("{}{}", if *flag { "" } else { "no" }, name)))
+            } else {
+                *flag = on;
+                Ok(None)
+            }
+        }
+    }
+
+    fn buffer_name(&self) -> String {
+        match self.buffer.path() {
+            Some(path) => path.display().to_string(),
+            None => "[No Name]".to_owned(),
+        }
+    }
+
+    fn window_title(&self) -> String {
+        let dirty = if self.buffer.is_dirty() { " [+]" } else { "" };
+        format!("{}{} - SnarkyEd", self.buffer_name(), dirty)
+    }
+
+    /// The editor's segments of the status line.
+    fn status(&self) -> Vec<Segment> {
+        let lines = self.buffer.get_lines_count();
+        let line = self.current_line();
+        let flag = |set: bool, flag: &str| if set { flag.to_owned() } else { String::new() };
+        let line_ending = if self.buffer.uses_crlf() {
+            "crlf"
+        } else {
+            "lf"
+        };
+        vec![
+            Segment::new("mode", self.mode.name().to_owned(), Side::Left),
+            Segment::new("file", self.buffer_name(), Side::Left),
+            Segment::new("modified", flag(self.buffer.is_dirty(), "[+]"), Side::Left),
+            Segment::new(
+                "read-only",
+                flag(self.buffer.is_read_only(), "[RO]"),
+                Side::Left,
+            ),
+            Segment::new(
+                "position",
+                format!("{}:{}", line + 1, self.screen_col(self.cursor.pos) + 1),
+                Side::Right,
+            ),
+            Segment::new(
+                "percent",
+                format!("{}%", (line + 1) * 100 / lines),
+                Side::Right,
+            ),
+            Segment::new("encoding", "utf-8".to_owned(), Side::Right),
+            Segment::new("line-ending", line_ending.to_owned(), Side::Right),
+        ]
+    }
+
+    fn move_cursor(&mut self, motion: Motion, count: Option<usize>) {
+        let n = count.unwrap_or(1);
+        match motion {
+            Motion::Up => (0..n).for_each(|_| self.move_cursor_up()),
+            Motion::Down => (0..n).for_each(|_| self.move_cursor_down()),
+            Motion::RowUp => self.move_by_rows(n, true),
+            Motion::RowDown => self.move_by_rows(n, false),
+            Motion::Left => (0..n).for_each(|_| self.move_cursor_left()),
+            Motion::Right => (0..n).for_each(|_| self.move_cursor_right()),
+            Motion::LineStart => self.move_to_bol(),
+            Motion::LineEnd => {
+                (1..n).for_each(|_| self.move_cursor_down());
+                self.move_to_eol();
+            }
+            _ => {
+                let pos = motion::target(&self.buffer, motion, self.cursor_position(), count);
+                self.set_cursor_position(pos);
+            }
+        }
+    }
+
+    /// The text `motion` moves over from the cursor.
+    fn motion_span(&self, motion: Motion, count: Option<usize>) -> Span {
+        let from = self.cursor_position();
+        let to = match motion {
+            Motion::RowUp | Motion::RowDown => {
+                let up = motion == Motion::RowUp;
+                let count = count.unwrap_or(1);
+                self.row_target(from, self.cursor.goal_col, count, up).0
+            }
+            _ => motion::target(&self.buffer, motion, from, count),
+        };
+        let range = Range::new(from, to);
+        match motion.kind() {
+            MotionKind::Linewise => Span::Lines(range.start.line, range.end.line),
+            MotionKind::Inclusive => {
+                let text = self.buffer.line_text(range.end.line);
+                let end = Position::new(
+                    range.end.line,
+                    grapheme::next_boundary(&text, range.end.col).min(text.chars().count()),
+                );
+                Span::Chars(Range::new(range.start, end))
+            }
+            MotionKind::Exclusive => {
+                // Like in vim, an exclusive motion that ends at the start of
+                // a later line stops at the end of the line before it.
+                if range.end.col == 0 && range.end.line > range.start.line {
+                    let line = range.end.line - 1;
+                    let end = Position::new(line, self.buffer.line_len(line));
+                    return Span::Chars(Range::new(range.start, end));
+                }
+                Span::Chars(range)
+            }
+        }
+    }
+
+    fn target_span(
+        &self,
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) -> Option<Span> {
+        let pos = self.cursor_position();
+        match target {
+            // `cw` on a word changes up to its end, leaving the blanks after
+            // it alone.
+            Target::Motion(Motion::NextWordStart(big))
+                if operator == Operator::Change
+                    && pos.col < self.buffer.line_len(pos.line)
+                    && !self
+                        .buffer
+                        .char_at(self.buffer.pos_to_char(pos))
+                        .is_whitespace() =>
+            {
+                let mut end = motion::current_word_end(&self.buffer, pos, big);
+                for _ in 1..count.unwrap_or(1) {
+                    end = motion::word_end(&self.buffer, end, big);
+                }
+                let text = self.buffer.line_text(end.line);
+                let end = Position::new(end.line, grapheme::next_boundary(&text, end.col));
+                Some(Span::Chars(Range::new(pos, end)))
+            }
+            Target::Motion(motion) => Some(self.motion_span(motion, count)),
+            Target::Object(object) => object.select(&self.buffer, pos),
+        }
+    }
+
+    /// The range covering lines `first` to `last` and their line breaks.
+    /// The last line has none, so the break before the range goes instead.
+    fn lines_range(&self, first: usize, last: usize) -> Range {
+        let last_line = self.buffer.get_lines_count() - 1;
+        if last < last_line {
+            Range::new(Position::new(first, 0), Position::new(last + 1, 0))
+        } else if first > 0 {
+            Range::new(
+                Position::new(first - 1, self.buffer.line_len(first - 1)),
+                Position::new(last, self.buffer.line_len(last)),
+            )
+        } else {
+            Range::new(
+                Position::new(first, 0),
+                Position::new(last, self.buffer.line_len(last)),
+            )
+        }
+    }
+
+    /// Stores the text `span` covers into the register called `name`.
+    fn yank(&mut self, span: Span, name: Option<char>, deleted: bool) {
+        let register = match span {
+            Span::Chars(range) => Register {
+                text: self.buffer.slice(range),
+                kind: RegisterKind::Charwise,
+            },
+            Span::Lines(first, last) => Register {
+                text: (first..=last)
+                    .map(|line| {
+                        let mut text = self.buffer.get_line_at(line);
+                        text.truncate(text.trim_end_matches(&['\r', '\n'][..]).len());
+                        text + "\n"
+                    })
+                    .collect(),
+                kind: RegisterKind::Linewise,
+            },
+            Span::Block(range) => Register {
+                text: (range.start.line..=range.end.line)
+                    .map(|line| self.buffer.slice(self.block_row(range, line)))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                kind: RegisterKind::Blockwise,
+            },
+        };
+        if let Err(message) = self.registers.store(name, register, deleted) {
+            self.actions.push(Action::ShowMessage(message.to_owned()));
+        }
+    }
+
+    /// The part of `line` inside the block `range`, whose columns are
+    /// screen columns.
+    fn block_row(&self, range: Range, line: usize) -> Range {
+        Range::new(
+            Position::new(line, self.col_at_screen(line, range.start.col)),
+            Position::new(line, self.col_at_screen(line, range.end.col)),
+        )
+    }
+
+    fn delete_block(&mut self, range: Range) {
+        for line in range.start.line..=range.end.line {
+            self.buffer.delete(self.block_row(range, line));
+        }
+    }
+
+    fn operate(&mut self, operator: Operator, span: Span, register: Option<char>) {
+        if operator != Operator::Yank && !self.check_modifiable() {
+            return;
+        }
+        let last_line = self.buffer.get_lines_count() - 1;
+        match (operator, span) {
+            (Operator::Yank, Span::Chars(range)) => {
+                self.yank(span, register, false);
+                self.set_cursor_position(range.start);
+            }
+            (Operator::Yank, Span::Lines(first, _)) => {
+                self.yank(span, register, false);
+                if first < self.current_line() {
+                    self.set_cursor_position(Position::new(first, self.cursor.pos.col));
+                }
+            }
+            (Operator::Yank, Span::Block(range)) => {
+                self.yank(span, register, false);
+                self.set_cursor_position(self.block_row(range, range.start.line).start);
+            }
+            (Operator::Delete, Span::Chars(range)) => {
+                self.yank(span, register, true);
+                let change = self.buffer.delete(range);
+                self.set_cursor_position(change.start);
+            }
+            (Operator::Delete, Span::Lines(first, last)) => {
+                self.yank(span, register, true);
+                self.buffer.delete(self.lines_range(first, last));
+                let line = first.min(self.buffer.get_lines_count() - 1);
+                self.set_cursor_position(motion::first_non_blank(&self.buffer, line));
+            }
+            (Operator::Delete, Span::Block(range)) => {
+                self.yank(span, register, true);
+                self.buffer.begin_undo_group(self.cursor_position());
+                self.delete_block(range);
+                self.buffer.end_undo_group();
+                self.set_cursor_position(self.block_row(range, range.start.line).start);
+            }
+            (Operator::Change, Span::Chars(range)) => {
+                self.yank(span, register, true);
+                self.buffer.begin_undo_group(self.cursor_position());
+                let change = self.buffer.delete(range);
+                self.enter_insert_mode();
+                self.set_cursor_position(change.start);
+            }
+            (Operator::Change, Span::Lines(first, last)) => {
+                // The lines are emptied rather than removed, keeping the
+                // indent of the first one.
+                self.yank(span, register, true);
+                self.buffer.begin_undo_group(self.cursor_position());
+                let indent = motion::first_non_blank(&self.buffer, first);
+                let indent_text = self
+                    .buffer
+                    .slice(Range::new(Position::new(first, 0), indent));
+                let end = Position::new(last, self.buffer.line_len(last));
+                let change = self
+                    .buffer
+                    .replace(Range::new(Position::new(first, 0), end), &indent_text);
+                self.enter_insert_mode();
+                self.set_cursor_position(change.new_end);
+            }
+            (Operator::Change, Span::Block(range)) => {
+                self.yank(span, register, true);
+                self.buffer.begin_undo_group(self.cursor_position());
+                self.delete_block(range);
+                self.enter_insert_mode();
+                self.set_cursor_position(self.block_row(range, range.start.line).start);
+                self.block_insert = Some(BlockInsert {
+                    first: range.start.line,
+                    last: range.end.line,
+                    col: self.screen_col(self.cursor.pos),
+                    line_len: self.buffer.line_len(range.start.line),
+                    pad: false,
+                });
+            }
+            (Operator::Indent, _) | (Operator::Outdent, _) => {
+                let (first, last) = match span {
+                    Span::Chars(range) | Span::Block(range) => (range.start.line, range.end.line),
+                    Span::Lines(first, last) => (first, last),
+                };
+                self.buffer.begin_undo_group(self.cursor_position());
+                for line in first..=last.min(last_line) {
+                    self.shift_line(line, operator == Operator::Outdent);
+                }
+                self.buffer.end_undo_group();
+                self.set_cursor_position(motion::first_non_blank(&self.buffer, first));
+            }
+        }
+    }
+
+    /// Indents `line` by `shiftwidth` screen columns, or takes that much
+    /// off its indent. The indent is rewritten with tabs or spaces as
+    /// `expandtab` says.
+    fn shift_line(&mut self, line: usize, outdent: bool) {
+        let text = self.buffer.line_text(line);
+        if text.is_empty() {
+            return;
+        }
+        let indent_len = text.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        let indent_width = self.screen_col(Position::new(line, indent_len));
+        let options = *self.buffer.options();
+        let width = if outdent {
+            indent_width.saturating_sub(options.shiftwidth)
+        } else {
+            indent_width + options.shiftwidth
+        };
+        let indent = options.indent(width);
+        if indent.chars().ne(text.chars().take(indent_len)) {
+            self.buffer.replace(
+                Range::new(Position::new(line, 0), Position::new(line, indent_len)),
+                &indent,
+            );
+        }
+    }
+
+    /// Puts the register after the cursor, or before it for `P`. Whole
+    /// lines go below or above the cursor line instead.
+    fn paste(&mut self, before: bool, count: usize, name: Option<char>) {
+        let register = match self.registers.get(name) {
+            Some(register) => register,
+            None => {
+                self.actions.push(Action::ShowMessage(format!(
+                    "Nothing in register {}",
+                    name.unwrap_or('"')
+                )));
+                return;
+            }
+        };
+        if !self.check_modifiable() {
+            return;
+        }
+        let pos = self.cursor_position();
+        self.buffer.begin_undo_group(pos);
+        if register.kind == RegisterKind::Blockwise {
+            self.paste_block(&register.text, before, count);
+            self.buffer.end_undo_group();
+            return;
+        }
+        let text = register.text.repeat(count);
+        if register.kind == RegisterKind::Linewise {
+            let line = if before { pos.line } else { pos.line + 1 };
+            if line < self.buffer.get_lines_count() {
+                self.buffer.insert(Position::new(line, 0), &text);
+            } else {
+                // Below the last line, which has no line break to insert
+                // after.
+                let eol = Position::new(pos.line, self.buffer.line_len(pos.line));
+                self.buffer
+                    .insert(eol, &format!("\n{}", text.trim_end_matches('\n')));
+            }
+            self.set_cursor_position(motion::first_non_blank(&self.buffer, line));
+        } else {
+            let line_text = self.buffer.line_text(pos.line);
+            let at = if before || line_text.is_empty() {
+                pos
+            } else {
+                Position::new(pos.line, grapheme::next_boundary(&line_text, pos.col))
+            };
+            let change = self.buffer.insert(at, &text);
+            let cursor = if text.contains('\n') {
+                change.start
+            } else {
+                let end = self.buffer.pos_to_char(change.new_end);
+                self.buffer.char_to_pos(end.saturating_sub(1))
+            };
+            self.set_cursor_position(cursor);
+        }
+        self.buffer.end_undo_group();
+    }
+
+    /// Puts the lines of a blockwise register in a column starting after
+    /// the cursor, or on it for `P`, padding short lines with spaces.
+    fn paste_block(&mut self, text: &str, before: bool, count: usize) {
+        let pos = self.cursor_position();
+        let line_text = self.buffer.line_text(pos.line);
+        let x = if before || line_text.is_empty() {
+            self.screen_col(pos)
+        } else {
+            self.screen_col(pos) + self.screen_width_at(pos)
+        };
+        let tabstop = self.tabstop();
+        let rows: Vec<&str> = text.split('\n').collect();
+        let width = rows
+            .iter()
+            .map(|row| grapheme::display_width(row, tabstop))
+            .max()
+            .unwrap_or(0);
+        for (i, row) in rows.iter().enumerate() {
+            let line = pos.line + i;
+            if line == self.buffer.get_lines_count() {
+                let eol = Position::new(line - 1, self.buffer.line_len(line - 1));
+                self.buffer.insert(eol, "\n");
+            }
+            let line_width = self.line_width(line);
+            let padding = width - grapheme::display_width(row, tabstop);
+            let mut block = (row.to_string() + &" ".repeat(padding)).repeat(count);
+            if line_width <= x {
+                // Nothing follows the block on this line to line up.
+                block.truncate(block.len() - padding);
+            }
+            let text = " ".repeat(x.saturating_sub(line_width)) + &block;
+            let col = self.col_at_screen(line, x);
+            self.buffer.insert(Position::new(line, col), &text);
+        }
+        self.set_cursor_position(Position::new(pos.line, self.col_at_screen(pos.line, x)));
+    }
+
+    fn execute(&mut self, args: Args, command: Command) {
+        let n = args.count.unwrap_or(1);
+        match command {
+            Command::Undo => (0..n).for_each(|_| self.undo()),
+            Command::Redo => (0..n).for_each(|_| self.redo()),
+            Command::Earlier => self.travel_history(-(n as isize)),
+            Command::Later => self.travel_history(n as isize),
+            Command::ScrollDown => self.scroll_down(10),
+            Command::ScrollUp => self.scroll_up(10),
+            Command::ScrollLeft => self.scroll_sideways(n, false),
+            Command::ScrollRight => self.scroll_sideways(n, true),
+            Command::ScrollCursorToStart => self.scroll_to_cursor(false),
+            Command::ScrollCursorToEnd => self.scroll_to_cursor(true),
+            Command::Save => self.save(),
+            Command::EnterCmdline => self.actions.push(Action::OpenCmdline(':')),
+            Command::Search(direction) => self.start_search(direction),
+            Command::SearchNext(reverse) => self.search_next(reverse, n),
+            Command::SearchWord(direction) => self.search_word(direction, n),
+            Command::Visual(kind) => self.toggle_visual(kind),
+            Command::LeaveVisual => self.leave_visual(),
+            Command::OperateSelection(operator) => self.operate_selection(operator, args.register),
+            Command::AddCursorAtNextMatch => (0..n).for_each(|_| self.add_cursor_at_next_match()),
+            Command::AddCursorBelow => self.add_cursor_vertically(true, n),
+            Command::AddCursorAbove => self.add_cursor_vertically(false, n),
+            Command::SingleCursor => self.other_cursors.clear(),
+            _ => self.for_each_cursor(|editor| editor.execute_at_cursor(args, command)),
+        }
+    }
+
+    /// Runs a command that applies to each cursor on its own.
+    fn execute_at_cursor(&mut self, args: Args, command: Command) {
+        let count = args.count;
+        let n = count.unwrap_or(1);
+        match command {
+            Command::Move(motion) => self.move_cursor(motion, count),
+            Command::Operate(operator, target) => {
+                if let Some(span) = self.target_span(operator, target, count) {
+                    self.operate(operator, span, args.register);
+                }
+            }
+            Command::OperateLines(operator) => {
+                let first = self.current_line();
+                let last = (first + n - 1).min(self.buffer.get_lines_count() - 1);
+                self.operate(operator, Span::Lines(first, last), args.register);
+            }
+            Command::InsertBefore => self.insert_before_cursor(),
+            Command::InsertAtBol => self.insert_at_bol(),
+            Command::Append => self.append_after_cursor(),
+            Command::AppendAtEol => self.append_at_eol(),
+            Command::OpenBelow => self.open_line_below(),
+            Command::OpenAbove => self.open_line_above(),
+            Command::DeleteChar => {
+                if let Span::Chars(range) = self.motion_span(Motion::Right, count) {
+                    if !range.is_empty() {
+                        self.operate(Operator::Delete, Span::Chars(range), args.register);
+                    }
+                }
+            }
+            Command::Paste(before) => self.paste(before, n, args.register),
+            Command::JoinLines => {
+                self.buffer.begin_undo_group(self.cursor_position());
+                (0..n.max(2) - 1).for_each(|_| self.join_with_next_line());
+                self.buffer.end_undo_group();
+            }
+            Command::SwapAnchor => self.swap_anchor(),
+            Command::SelectObject(object) => self.select_object(object),
+            Command::BlockInsert(append) => self.block_insert(append),
+            // The rest act on the view as a whole, see `execute`.
+            _ => (),
+        }
+    }
+
+    fn feed_key(&mut self, key: Key) {
+        if let Parse::Done(args, command) = self.keys.feed(key, self.mode, Instant::now()) {
+            self.execute(args, command);
+        }
+    }
+
+    fn handle_normal_input(&mut self, key_code: VirtualKeyCode, modifiers: ModifiersState) {
+        // Printable keys arrive through `push_char`.
+        let key = match (key_code, modifiers) {
+            (VirtualKeyCode::Escape, _) => Key::Escape,
+            (VirtualKeyCode::Up, NO_MODIFIERS) => Key::Up,
+            (VirtualKeyCode::Down, NO_MODIFIERS) => Key::Down,
+            (VirtualKeyCode::Left, NO_MODIFIERS) => Key::Left,
+            (VirtualKeyCode::Right, NO_MODIFIERS) => Key::Right,
+            (VirtualKeyCode::Up, CTRL_HOLD) => Key::CtrlUp,
+            (VirtualKeyCode::Down, CTRL_HOLD) => Key::CtrlDown,
+            (key_code, CTRL_HOLD) => match letter(key_code) {
+                Some(c) => Key::Ctrl(c),
+                None => return,
+            },
+            _ => return,
+        };
+        self.feed_key(key);
+    }
+
+    fn handle_insert_input(&mut self, key_code: VirtualKeyCode, modifiers: ModifiersState) {
+        match (key_code, modifiers) {
+            (VirtualKeyCode::Escape, NO_MODIFIERS) => {
+                self.leave_insert_mode();
+            }
+            (VirtualKeyCode::S, CTRL_HOLD) => {
+                self.break_undo_group();
+                self.save();
+            }
+            (VirtualKeyCode::Left, NO_MODIFIERS) => {
+                self.for_each_cursor(Self::move_cursor_left);
+                self.break_undo_group();
+            }
+            (VirtualKeyCode::Right, NO_MODIFIERS) => {
+                self.for_each_cursor(Self::move_cursor_right);
+                self.break_undo_group();
+            }
+            (VirtualKeyCode::Up, NO_MODIFIERS) => {
+                self.for_each_cursor(Self::move_cursor_up);
+                self.break_undo_group();
+            }
+            (VirtualKeyCode::Down, NO_MODIFIERS) => {
+                self.for_each_cursor(Self::move_cursor_down);
+                self.break_undo_group();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Tells the user `file` was opened read-only because it isn't UTF-8.
+fn read_only_notice(file: &str) -> Action {
+    Action::ShowMessage(format!("\"{}\": not valid UTF-8, opened read-only", file))
+}
+
+/// The count after a command like `:earlier`, 1 if there is none.
+fn count_argument(command: &ExCommand) -> Result<isize, ExError> {
+    match command.argument {
+        Some(count) => count
+            .parse()
+            .map_err(|_| ExError::InvalidArgument(count.to_owned())),
+        None => Ok(1),
+    }
+}
+
+/// The lowercase letter on a letter key.
+fn letter(key_code: VirtualKeyCode) -> Option<char> {
+    let a = VirtualKeyCode::A as u32;
+    let code = key_code as u32;
+    if code >= a && code <= VirtualKeyCode::Z as u32 {
+        std::char::from_u32('a' as u32 + code - a)
+    } else {
+        None
     }
 }
 
@@ -137,48 +2182,184 @@ impl<'a, 'b> View for EditorView<'a, 'b> {
         self.font_size = BASE_FONT_SIZE * hidpi_factor;
 
         let screen_dims = display.get_framebuffer_dimensions();
-        self.viewport_rows = (screen_dims.1 as f32 / self.font_size) as usize;
+        let status_line_height = STATUS_LINE_HEIGHT * hidpi_factor;
+        self.scroll.rows = ((screen_dims.1 as f32 - status_line_height) / self.font_size) as usize;
+        let gutter_columns = self.gutter.width(&self.gutter_context());
+        let gutter_width = self.letter_size.width() * gutter_columns as f32;
+        let text_x = self.padding / 2.0 + gutter_width;
+        let text_bounds = (
+            screen_dims.0 as f32 - self.padding - gutter_width,
+            screen_dims.1 as f32 - status_line_height,
+        );
+        let columns = ((screen_dims.0 as f32 - self.padding) / self.letter_size.width()) as usize;
+        self.scroll.columns = columns.saturating_sub(gutter_columns);
+        let mut scroll = self.scroll.clone();
+        scroll.keep_visible(self.current_line(), self.buffer.get_lines_count(), |line| {
+            self.line_height(line)
+        });
+        self.scroll = scroll;
+        self.scroll.keep_column_visible(
+            self.screen_col(self.cursor.pos),
+            self.screen_width_at(self.cursor.pos),
+        );
+        self.keys.expire(Instant::now());
 
-        let content_to_draw = self
-            .buffer
-            .get_lines(self.offset_y, self.offset_y + self.viewport_rows);
-        let whitespace_content_to_draw = self
-            .buffer
-            .get_lines(self.offset_y, self.offset_y + self.viewport_rows)
-            .replace(' ', "·");
+        let title = self.window_title();
+        if title != self.title {
+            display.gl_window().window().set_title(&title);
+            self.title = title;
+        }
+
+        let status = self.status();
+        for (i, segment) in status.iter().enumerate() {
+            if self.status.get(i) != Some(segment) {
+                self.actions.push(Action::SetStatus(segment.clone()));
+            }
+        }
+        self.status = status;
+
+        // Rows are laid out here rather than left to glyph_brush, so they
+        // break where the cursor math expects. Tabs are drawn as spaces up to
+        // the next tab stop, with a marker in the whitespace overlay, and
+        // rows that go on to the next one end with a wrap indicator.
+        let tabstop = self.tabstop();
+        let rows = self.screen_rows();
+        let mut content_rows = Vec::new();
+        let mut whitespace_rows = Vec::new();
+        for row in rows.iter() {
+            let text = self.buffer.line_text(row.line);
+            // A tab or wide char cut off at the left edge leaves blanks.
+            let left = self.row_left(row);
+            let mut start = row.start.max(self.col_at_screen(row.line, left));
+            if self.screen_col(Position::new(row.line, start)) < left {
+                start = grapheme::next_boundary(&text, start);
+            }
+            let end = row
+                .end
+                .min(self.col_at_screen(row.line, left + self.scroll.columns));
+            let blank = " ".repeat(
+                self.screen_col(Position::new(row.line, start))
+                    .saturating_sub(left),
+            );
+            content_rows
+                .push(blank.clone() + &grapheme::expand_tabs(&text, start, end, tabstop, ' '));
+            let mut whitespace =
+                blank + &grapheme::expand_tabs(&text.replace(' ', "·"), start, end, tabstop, '→');
+            if !row.last {
+                let width = self.screen_col(Position::new(row.line, row.end)) - row.x;
+                whitespace.push_str(&" ".repeat(self.scroll.wrap_width().saturating_sub(width)));
+                whitespace.push('»');
+            }
+            whitespace_rows.push(whitespace);
+        }
+        let content_to_draw = content_rows.join("\n");
+        let whitespace_content_to_draw = whitespace_rows.join("\n");
+        let (current_line_number, line_numbers) = self.gutter.render(&self.gutter_context(), &rows);
 
         self.glyph_brush.queue(Section {
-            text: &whitespace_content_to_draw,
-            bounds: (screen_dims.0 as f32 - self.padding, screen_dims.1 as f32),
+            text: &line_numbers,
             screen_position: ((self.padding / 2.0), (self.padding / 2.0)),
             scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
-            color: ui::color::hex("#293940").as_slice(),
+            color: ui::color::hex("#3E5863").as_slice(),
             ..Section::default()
         });
 
         self.glyph_brush.queue(Section {
-            text: &content_to_draw,
-            bounds: (screen_dims.0 as f32 - self.padding, screen_dims.1 as f32),
+            text: &current_line_number,
             screen_position: ((self.padding / 2.0), (self.padding / 2.0)),
             scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
             color: ui::color::hex("#E6FFFF").as_slice(),
             ..Section::default()
         });
 
+        let cursors: Vec<Cursor> = self
+            .other_cursors
+            .iter()
+            .cloned()
+            .chain(std::iter::once(self.cursor))
+            .collect();
+
+        if let Mode::Visual(kind) = self.mode {
+            for cursor in cursors.iter() {
+                let span = self.selection(kind, cursor.anchor, cursor.pos);
+                let highlight = self.selection_highlight(span, &rows);
+                self.glyph_brush.queue(Section {
+                    text: &highlight,
+                    bounds: text_bounds,
+                    screen_position: (text_x, (self.padding / 2.0)),
+                    scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+                    color: ui::color::hexa("#3A60D7", 0.3).as_slice(),
+                    ..Section::default()
+                });
+            }
+        }
+
+        if let Some(highlight) = self.search_highlight(&rows) {
+            self.glyph_brush.queue(Section {
+                text: &highlight,
+                bounds: text_bounds,
+                screen_position: (text_x, (self.padding / 2.0)),
+                scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+                color: ui::color::hexa("#FFCB6B", 0.25).as_slice(),
+                ..Section::default()
+            });
+        }
+        if let Some(current) = self.current_match {
+            let highlight = self.selection_highlight(Span::Chars(current), &rows);
+            self.glyph_brush.queue(Section {
+                text: &highlight,
+                bounds: text_bounds,
+                screen_position: (text_x, (self.padding / 2.0)),
+                scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+                color: ui::color::hexa("#FFCB6B", 0.5).as_slice(),
+                ..Section::default()
+            });
+        }
+
         self.glyph_brush.queue(Section {
-            text: "█",
-            bounds: (
-                screen_dims.0 as f32 - self.padding,
-                screen_dims.1 as f32 - self.padding,
-            ),
-            screen_position: (
-                (self.padding / 2.0) + (self.letter_size.width() * self.cursor.col as f32),
-                (self.padding / 2.0) + (self.letter_size.height() * self.cursor.row as f32),
-            ),
+            text: &whitespace_content_to_draw,
+            bounds: text_bounds,
+            screen_position: (text_x, (self.padding / 2.0)),
             scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
-            color: ui::color::hexa("#3A60D7", 0.4).as_slice(),
+            color: ui::color::hex("#293940").as_slice(),
             ..Section::default()
         });
+
+        self.glyph_brush.queue(Section {
+            text: &content_to_draw,
+            bounds: text_bounds,
+            screen_position: (text_x, (self.padding / 2.0)),
+            scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+            color: ui::color::hex("#E6FFFF").as_slice(),
+            ..Section::default()
+        });
+
+        for cursor in cursors.iter() {
+            let row = match rows.iter().position(|row| row.contains(cursor.pos)) {
+                Some(row) => row,
+                None => continue,
+            };
+            let x = match self
+                .screen_col(cursor.pos)
+                .checked_sub(self.row_left(&rows[row]))
+            {
+                Some(x) if x < self.scroll.columns => x,
+                _ => continue,
+            };
+            // A wide char or a tab gets a cursor as wide as it is.
+            let block = "█".repeat(self.screen_width_at(cursor.pos));
+            self.glyph_brush.queue(Section {
+                text: &block,
+                bounds: (text_bounds.0, text_bounds.1 - self.padding),
+                screen_position: (
+                    text_x + (self.letter_size.width() * x as f32),
+                    (self.padding / 2.0) + (self.letter_size.height() * row as f32),
+                ),
+                scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+                color: ui::color::hexa("#3A60D7", 0.4).as_slice(),
+                ..Section::default()
+            });
+        }
     }
 
     fn draw(&mut self, display: &Display, target: &mut Frame) {
@@ -191,36 +2372,83 @@ impl<'a, 'b> View for EditorView<'a, 'b> {
         state: ElementState,
         modifiers: ModifiersState,
     ) {
-        match (key_code, state, modifiers) {
-            (VirtualKeyCode::J, ElementState::Pressed, NO_MODIFIERS) => {
-                self.move_cursor_down();
-            }
-            (VirtualKeyCode::K, ElementState::Pressed, NO_MODIFIERS) => {
-                self.move_cursor_up();
-            }
-            (VirtualKeyCode::H, ElementState::Pressed, NO_MODIFIERS) => {
-                self.move_cursor_left();
-            }
-            (VirtualKeyCode::L, ElementState::Pressed, NO_MODIFIERS) => {
-                self.move_cursor_right();
+        if state != ElementState::Pressed {
+            return;
+        }
+        if self.confirming.is_some() {
+            if key_code == VirtualKeyCode::Escape {
+                self.answer_confirm('q');
             }
-            (VirtualKeyCode::Key0, ElementState::Pressed, NO_MODIFIERS) => {
-                self.move_to_bol();
+            return;
+        }
+        match self.mode {
+            Mode::Normal | Mode::Visual(_) => self.handle_normal_input(key_code, modifiers),
+            Mode::Insert => self.handle_insert_input(key_code, modifiers),
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        if self.confirming.is_some() {
+            self.answer_confirm(c);
+            return;
+        }
+        if self.mode != Mode::Insert {
+            if !c.is_control() {
+                self.feed_key(Key::Char(c));
             }
-            (VirtualKeyCode::Key4, ElementState::Pressed, SHIFT_HOLD) => {
-                self.move_to_eol(false);
+            return;
+        }
+        match c {
+            '\r' | '\n' => self.for_each_cursor(Self::insert_newline),
+            '\t' => self.for_each_cursor(Self::insert_tab),
+            c if c.is_control() => (),
+            c => self.for_each_cursor(|editor| editor.insert_char(c)),
+        }
+    }
+
+    fn pop_char(&mut self) {
+        if self.mode == Mode::Insert {
+            self.for_each_cursor(Self::delete_char_before_cursor);
+        }
+    }
+
+    /// Only while a `:s` with the confirm flag waits for an answer.
+    fn captures_input(&self) -> bool {
+        self.confirming.is_some()
+    }
+
+    fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    fn dispatch(&mut self, action: &Action) {
+        match action {
+            Action::RunCommand(text) if self.incsearch.is_some() => self.finish_search(text),
+            Action::RunCommand(command) => self.run_command(command),
+            Action::CmdlineChanged(text) => self.update_search(text),
+            Action::CmdlineCancelled => self.cancel_search(),
+            Action::Complete(text) => {
+                let completions = self.ex_commands.complete(text, OPTIONS);
+                self.actions.push(Action::Completions(completions));
             }
-            (VirtualKeyCode::J, ElementState::Pressed, CTRL_HOLD) => {
-                self.scroll_down(10);
+            Action::OpenFile(file) => {
+                if let Err(err) = self.open_file(file) {
+                    self.actions.push(Action::ShowError(err.to_string()));
+                }
             }
-            (VirtualKeyCode::K, ElementState::Pressed, CTRL_HOLD) => {
-                self.scroll_up(10);
+            Action::ListCommands => {
+                let entries = self
+                    .ex_commands
+                    .list()
+                    .into_iter()
+                    .map(|(name, keys)| Entry {
+                        name,
+                        keys: keys.map(String::from),
+                    })
+                    .collect();
+                self.actions.push(Action::CommandList(entries));
             }
             _ => (),
         }
     }
-
-    fn push_char(&mut self, c: char) {}
-
-    fn pop_char(&mut self) {}
 }
@@ -0,0 +1,42 @@
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual(VisualKind),
+}
+
+impl Mode {
+    /// How the mode is shown in the status line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual(VisualKind::Char) => "VISUAL",
+            Mode::Visual(VisualKind::Line) => "V-LINE",
+            Mode::Visual(VisualKind::Block) => "V-BLOCK",
+        }
+    }
+}
+
+/// What a visual mode selects between the anchor and the cursor: the chars
+/// in between, whole lines, or a rectangle with them at opposite corners.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum VisualKind {
+    Char,
+    Line,
+    Block,
+}
+
+/// A block mode `I`, `A` or `c` in progress. What gets typed on the first
+/// line is put on the other ones too when insert mode ends.
+pub struct BlockInsert {
+    pub first: usize,
+    pub last: usize,
+    /// The screen column the text goes in at.
+    pub col: usize,
+    /// The length of the first line when insert mode started.
+    pub line_len: usize,
+    /// Whether lines too short to reach `col` are padded with spaces
+    /// rather than skipped.
+    pub pad: bool,
+}
@@ -0,0 +1,374 @@
+use super::buffer::Buffer;
+use super::grapheme;
+use super::range::{Position, Range};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `gk`, up a row on screen when lines wrap.
+    RowUp,
+    /// `gj`, down a row on screen when lines wrap.
+    RowDown,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// `w`, or `W` when true.
+    NextWordStart(bool),
+    /// `b`, or `B` when true.
+    PrevWordStart(bool),
+    /// `e`, or `E` when true.
+    WordEnd(bool),
+    /// `gg`, or line N with a count.
+    FirstLine,
+    /// `G`, or line N with a count.
+    LastLine,
+}
+
+/// How an operator treats the text a motion moves over.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MotionKind {
+    /// Up to, but not including, the target.
+    Exclusive,
+    /// Up to and including the target.
+    Inclusive,
+    /// Every line between the cursor and the target, whole.
+    Linewise,
+}
+
+impl Motion {
+    pub fn kind(self) -> MotionKind {
+        match self {
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine => {
+                MotionKind::Linewise
+            }
+            Motion::LineEnd | Motion::WordEnd(_) => MotionKind::Inclusive,
+            _ => MotionKind::Exclusive,
+        }
+    }
+}
+
+/// Where `motion`, repeated `count` times, takes the cursor from `pos`. For
+/// `gg` and `G` the count is a line number instead.
+pub fn target(buffer: &Buffer, motion: Motion, pos: Position, count: Option<usize>) -> Position {
+    let n = count.unwrap_or(1);
+    let last_line = buffer.get_lines_count() - 1;
+    match motion {
+        Motion::Left => {
+            let text = buffer.line_text(pos.line);
+            let col = (0..n).fold(pos.col, |col, _| grapheme::prev_boundary(&text, col));
+            Position::new(pos.line, col)
+        }
+        Motion::Right => {
+            let text = buffer.line_text(pos.line);
+            let col = (0..n).fold(pos.col, |col, _| grapheme::next_boundary(&text, col));
+            Position::new(pos.line, col.min(buffer.line_len(pos.line)))
+        }
+        // Where rows are is up to the view, which moves over them itself.
+        // Without one they are lines.
+        Motion::Up | Motion::RowUp => {
+            buffer.clamp(Position::new(pos.line.saturating_sub(n), pos.col))
+        }
+        Motion::Down | Motion::RowDown => {
+            buffer.clamp(Position::new((pos.line + n).min(last_line), pos.col))
+        }
+        Motion::LineStart => Position::new(pos.line, 0),
+        Motion::FirstNonBlank => first_non_blank(buffer, pos.line),
+        Motion::LineEnd => {
+            let line = (pos.line + n - 1).min(last_line);
+            let end = grapheme::prev_boundary(&buffer.line_text(line), buffer.line_len(line));
+            Position::new(line, end)
+        }
+        Motion::NextWordStart(big) => repeat(buffer, pos, n, big, next_word_start),
+        Motion::PrevWordStart(big) => repeat(buffer, pos, n, big, prev_word_start),
+        Motion::WordEnd(big) => repeat(buffer, pos, n, big, word_end),
+        Motion::FirstLine => first_non_blank(buffer, (n - 1).min(last_line)),
+        Motion::LastLine => {
+            let line = count.map(|n| n.saturating_sub(1)).unwrap_or(last_line);
+            first_non_blank(buffer, line.min(last_line))
+        }
+    }
+}
+
+fn repeat(
+    buffer: &Buffer,
+    pos: Position,
+    count: usize,
+    big: bool,
+    motion: fn(&Buffer, Position, bool) -> Position,
+) -> Position {
+    (0..count).fold(pos, |pos, _| motion(buffer, pos, big))
+}
+
+/// The first char on `line` that isn't a space or a tab.
+pub fn first_non_blank(buffer: &Buffer, line: usize) -> Position {
+    let indent = buffer
+        .get_line_at(line)
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .count();
+    Position::new(line, indent.min(buffer.line_len(line)))
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum CharClass {
+    Blank,
+    Punctuation,
+    Keyword,
+}
+
+/// Vim's character classes: a word is a run of keyword chars or a run of
+/// other non-blank chars. For WORDs (`big`) every non-blank char is the same
+/// class.
+fn class_of(c: char, big: bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if big || c.is_alphanumeric() || c == '_' {
+        CharClass::Keyword
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// The word of keyword chars under `pos`, if it is on one.
+pub fn keyword_under(buffer: &Buffer, pos: Position) -> Option<Range> {
+    let line: Vec<char> = buffer
+        .get_line_at(pos.line)
+        .chars()
+        .take(buffer.line_len(pos.line))
+        .collect();
+    let is_keyword = |col: usize| class_of(line[col], false) == CharClass::Keyword;
+    if pos.col >= line.len() || !is_keyword(pos.col) {
+        return None;
+    }
+    let mut start = pos.col;
+    while start > 0 && is_keyword(start - 1) {
+        start -= 1;
+    }
+    let mut end = pos.col + 1;
+    while end < line.len() && is_keyword(end) {
+        end += 1;
+    }
+    Some(Range::new(
+        Position::new(pos.line, start),
+        Position::new(pos.line, end),
+    ))
+}
+
+/// The start of the next match of `word` as a whole word after `pos`,
+/// wrapping around the end of the buffer. Lands back on `pos` when that is
+/// the only match.
+pub fn next_word_match(buffer: &Buffer, word: &str, pos: Position) -> Option<Position> {
+    let word: Vec<char> = word.chars().collect();
+    let lines = buffer.get_lines_count();
+    for i in 0..=lines {
+        let line = (pos.line + i) % lines;
+        let text: Vec<char> = buffer
+            .get_line_at(line)
+            .chars()
+            .take(buffer.line_len(line))
+            .collect();
+        let is_keyword = |col: usize| class_of(text[col], false) == CharClass::Keyword;
+        for col in 0..(text.len() + 1).saturating_sub(word.len()) {
+            let after_pos = if i == 0 {
+                col > pos.col
+            } else {
+                i < lines || col <= pos.col
+            };
+            let end = col + word.len();
+            if after_pos
+                && text[col..end] == word[..]
+                && (col == 0 || !is_keyword(col - 1))
+                && (end == text.len() || !is_keyword(end))
+            {
+                return Some(Position::new(line, col));
+            }
+        }
+    }
+    None
+}
+
+/// Whether `idx` is the start of an empty line, which vim treats as a word
+/// of its own. A `\r\n` line break makes a line no less empty.
+fn is_empty_line(buffer: &Buffer, idx: usize) -> bool {
+    let pos = buffer.char_to_pos(idx);
+    pos.col == 0 && buffer.line_len(pos.line) == 0
+}
+
+/// `w` / `W`: the start of the next word, or the end of the buffer after
+/// the last one, so an operator takes in its last char.
+pub fn next_word_start(buffer: &Buffer, pos: Position, big: bool) -> Position {
+    let len = buffer.len_chars();
+    let mut idx = buffer.pos_to_char(pos);
+    if idx >= len {
+        return pos;
+    }
+    let class = class_of(buffer.char_at(idx), big);
+    if class != CharClass::Blank {
+        while idx < len && class_of(buffer.char_at(idx), big) == class {
+            idx += 1;
+        }
+    }
+    while idx < len && class_of(buffer.char_at(idx), big) == CharClass::Blank {
+        idx += 1;
+        if idx < len && is_empty_line(buffer, idx) {
+            break;
+        }
+    }
+    buffer.char_to_pos(idx)
+}
+
+/// `e` / `E`: the end of the current or next word.
+pub fn word_end(buffer: &Buffer, pos: Position, big: bool) -> Position {
+    let len = buffer.len_chars();
+    let mut idx = buffer.pos_to_char(pos) + 1;
+    while idx < len && class_of(buffer.char_at(idx), big) == CharClass::Blank {
+        idx += 1;
+    }
+    if idx >= len {
+        return buffer.char_to_pos(len.saturating_sub(1));
+    }
+    let class = class_of(buffer.char_at(idx), big);
+    while idx + 1 < len && class_of(buffer.char_at(idx + 1), big) == class {
+        idx += 1;
+    }
+    buffer.char_to_pos(idx)
+}
+
+/// The last char of the word under `pos`, which is where `cw` changes to
+/// instead of the start of the next word.
+pub fn current_word_end(buffer: &Buffer, pos: Position, big: bool) -> Position {
+    let len = buffer.len_chars();
+    let mut idx = buffer.pos_to_char(pos);
+    if idx >= len {
+        return pos;
+    }
+    let class = class_of(buffer.char_at(idx), big);
+    while idx + 1 < len && class_of(buffer.char_at(idx + 1), big) == class {
+        idx += 1;
+    }
+    buffer.char_to_pos(idx)
+}
+
+/// `b` / `B`: the start of the current or previous word.
+pub fn prev_word_start(buffer: &Buffer, pos: Position, big: bool) -> Position {
+    let start = buffer.pos_to_char(pos);
+    if start == 0 {
+        return pos;
+    }
+    let mut idx = start - 1;
+    while class_of(buffer.char_at(idx), big) == CharClass::Blank {
+        if idx == 0 || is_empty_line(buffer, idx) {
+            return buffer.char_to_pos(idx);
+        }
+        idx -= 1;
+    }
+    let class = class_of(buffer.char_at(idx), big);
+    while idx > 0 && class_of(buffer.char_at(idx - 1), big) == class {
+        idx -= 1;
+    }
+    buffer.char_to_pos(idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        Buffer::from_reader(text.as_bytes()).unwrap()
+    }
+
+    /// Where `motion`, `count` times, takes the cursor from `line`, `col`.
+    fn go(
+        text: &str,
+        motion: Motion,
+        (line, col): (usize, usize),
+        count: Option<usize>,
+    ) -> (usize, usize) {
+        let pos = target(&buffer(text), motion, Position::new(line, col), count);
+        (pos.line, pos.col)
+    }
+
+    #[test]
+    fn empty_lines_are_words_with_either_line_break() {
+        for text in &["ab\n\ncd", "ab\r\n\r\ncd"] {
+            let w = Motion::NextWordStart(false);
+            let b = Motion::PrevWordStart(false);
+            assert_eq!(go(text, w, (0, 0), None), (1, 0), "{:?}", text);
+            assert_eq!(go(text, w, (1, 0), None), (2, 0), "{:?}", text);
+            assert_eq!(go(text, b, (2, 0), None), (1, 0), "{:?}", text);
+            assert_eq!(go(text, b, (1, 0), None), (0, 0), "{:?}", text);
+            assert_eq!(go(text, Motion::WordEnd(false), (0, 1), None), (2, 1));
+        }
+        // A line of blanks isn't empty.
+        let w = Motion::NextWordStart(false);
+        assert_eq!(go("ab\n  \ncd", w, (0, 0), None), (2, 0));
+    }
+
+    #[test]
+    fn w_after_the_last_word_ends_past_it() {
+        let w = Motion::NextWordStart(false);
+        assert_eq!(go("foo bar", w, (0, 4), None), (0, 7));
+        assert_eq!(go("foo bar", w, (0, 7), None), (0, 7));
+        assert_eq!(go("foo bar.", w, (0, 4), Some(5)), (0, 8));
+        // Past a final line break is the empty line after it.
+        assert_eq!(go("foo bar\n", w, (0, 4), None), (1, 0));
+    }
+
+    const CODE: &str = "foo.bar baz(qux)";
+
+    #[test]
+    fn words_split_keywords_from_punctuation() {
+        let w = Motion::NextWordStart(false);
+        let stops: Vec<usize> = [0, 3, 4, 8, 11, 12, 15]
+            .iter()
+            .map(|col| go(CODE, w, (0, *col), None).1)
+            .collect();
+        assert_eq!(stops, vec![3, 4, 8, 11, 12, 15, 16]);
+        assert_eq!(go("a_1+b", w, (0, 0), None), (0, 3));
+        assert_eq!(go("foo\n  bar", w, (0, 0), None), (1, 2));
+
+        let e = Motion::WordEnd(false);
+        assert_eq!(go(CODE, e, (0, 0), None), (0, 2));
+        assert_eq!(go(CODE, e, (0, 2), None), (0, 3));
+        assert_eq!(go(CODE, e, (0, 3), None), (0, 6));
+        assert_eq!(go(CODE, e, (0, 15), None), (0, 15));
+
+        let b = Motion::PrevWordStart(false);
+        assert_eq!(go(CODE, b, (0, 15), None), (0, 12));
+        assert_eq!(go(CODE, b, (0, 12), None), (0, 11));
+        assert_eq!(go(CODE, b, (0, 8), None), (0, 4));
+        assert_eq!(go(CODE, b, (0, 0), None), (0, 0));
+        assert_eq!(go("foo\n  bar", b, (1, 2), None), (0, 0));
+    }
+
+    #[test]
+    fn big_words_are_separated_by_blanks_only() {
+        assert_eq!(go(CODE, Motion::NextWordStart(true), (0, 0), None), (0, 8));
+        assert_eq!(go(CODE, Motion::NextWordStart(true), (0, 8), None), (0, 16));
+        assert_eq!(go(CODE, Motion::WordEnd(true), (0, 0), None), (0, 6));
+        assert_eq!(go(CODE, Motion::WordEnd(true), (0, 6), None), (0, 15));
+        assert_eq!(go(CODE, Motion::PrevWordStart(true), (0, 15), None), (0, 8));
+        assert_eq!(go(CODE, Motion::PrevWordStart(true), (0, 8), None), (0, 0));
+    }
+
+    #[test]
+    fn counts_repeat_the_motion() {
+        assert_eq!(
+            go(CODE, Motion::NextWordStart(false), (0, 0), Some(3)),
+            (0, 8)
+        );
+        assert_eq!(
+            go(CODE, Motion::PrevWordStart(false), (0, 15), Some(2)),
+            (0, 11)
+        );
+        assert_eq!(go(CODE, Motion::WordEnd(false), (0, 0), Some(2)), (0, 3));
+        assert_eq!(go(CODE, Motion::WordEnd(false), (0, 0), Some(99)), (0, 15));
+        let text = "a\n  b\nc";
+        assert_eq!(go(text, Motion::LastLine, (0, 0), None), (2, 0));
+        assert_eq!(go(text, Motion::LastLine, (0, 0), Some(2)), (1, 2));
+        assert_eq!(go(text, Motion::FirstLine, (2, 0), Some(9)), (2, 0));
+    }
+}
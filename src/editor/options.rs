@@ -0,0 +1,32 @@
+use crate::constants::{EXPAND_TAB, SHIFT_WIDTH, TAB_STOP};
+
+/// The settings each buffer keeps its own value of.
+#[derive(Copy, Clone, Debug)]
+pub struct Options {
+    /// How many screen columns apart tab stops are.
+    pub tabstop: usize,
+    /// How many screen columns `>` and `<` shift lines by.
+    pub shiftwidth: usize,
+    /// Whether indenting and the tab key put in spaces rather than tabs.
+    pub expandtab: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            tabstop: TAB_STOP,
+            shiftwidth: SHIFT_WIDTH,
+            expandtab: EXPAND_TAB,
+        }
+    }
+
+    /// Whitespace that indents a line to screen column `width`, with as
+    /// many tabs as fit unless `expandtab` is set.
+    pub fn indent(&self, width: usize) -> String {
+        if self.expandtab {
+            " ".repeat(width)
+        } else {
+            "\t".repeat(width / self.tabstop) + &" ".repeat(width % self.tabstop)
+        }
+    }
+}
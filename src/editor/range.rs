@@ -0,0 +1,73 @@
+/// A location in a buffer: a zero-based line and a char offset into it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, col: usize) -> Position {
+        Position { line, col }
+    }
+}
+
+/// A half-open span of text between two positions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn new(a: Position, b: Position) -> Range {
+        if a <= b {
+            Range { start: a, end: b }
+        } else {
+            Range { start: b, end: a }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// The text an operator acts on: a range of chars, whole lines from the
+/// first to the last one given, or a block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Span {
+    Chars(Range),
+    Lines(usize, usize),
+    /// Lines `start.line` to `end.line`, from screen column `start.col` up
+    /// to but not including `end.col` on each. Shorter lines give what they
+    /// have.
+    Block(Range),
+}
+
+/// Describes what an edit did to a buffer. The text between `start` and
+/// `old_end` was replaced by the text now between `start` and `new_end`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub start: Position,
+    pub old_end: Position,
+    pub new_end: Position,
+}
+
+impl Change {
+    /// Where `pos` ends up after the change. A position inside the replaced
+    /// text goes to its start.
+    pub fn map(&self, pos: Position) -> Position {
+        if pos < self.start {
+            pos
+        } else if pos < self.old_end {
+            self.start
+        } else if pos.line == self.old_end.line {
+            Position::new(
+                self.new_end.line,
+                self.new_end.col + pos.col - self.old_end.col,
+            )
+        } else {
+            Position::new(pos.line - self.old_end.line + self.new_end.line, pos.col)
+        }
+    }
+}
@@ -0,0 +1,294 @@
+extern crate arboard;
+use arboard::Clipboard;
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RegisterKind {
+    Charwise,
+    /// Whole lines, which are put above or below the cursor line rather
+    /// than inside it.
+    Linewise,
+    /// A rectangle of text, one line of it per buffer line, which is put
+    /// in a column starting at the cursor.
+    Blockwise,
+}
+
+/// Text that was yanked or deleted, ready to be put back.
+#[derive(Clone, Debug)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+/// Vim's registers: `""` holds the last text yanked or deleted into any of
+/// them, `"a` to `"z` are set by name (`"A` to `"Z` append), `"0` holds the
+/// last yank, `"1` to `"9` the last deletes of lines, `"-` the last small
+/// delete, `"_` drops whatever is written to it, `"+` is the system
+/// clipboard and `"*` the primary selection on X11 (the clipboard
+/// elsewhere).
+pub struct Registers {
+    unnamed: Option<Register>,
+    named: HashMap<char, Register>,
+    last_yank: Option<Register>,
+    /// `"1` to `"9`, most recent first.
+    deletes: [Option<Register>; 9],
+    clipboard: Option<Clipboard>,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers {
+            unnamed: None,
+            named: HashMap::new(),
+            last_yank: None,
+            deletes: Default::default(),
+            clipboard: None,
+        }
+    }
+
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_alphanumeric() || "\"-_+*".contains(name)
+    }
+
+    /// Stores text that was yanked, or deleted when `deleted` is set, into
+    /// the register called `name` (or the default ones when there is none).
+    /// Fails if the text can't be put on the system clipboard.
+    pub fn store(
+        &mut self,
+        name: Option<char>,
+        register: Register,
+        deleted: bool,
+    ) -> Result<(), &'static str> {
+        let mut result = Ok(());
+        match name {
+            Some('_') => return result,
+            Some(c) if c.is_ascii_lowercase() || c == '-' => {
+                self.named.insert(c, register.clone());
+            }
+            Some(c) if c.is_ascii_uppercase() => {
+                let name = c.to_ascii_lowercase();
+                let register = match self.named.remove(&name) {
+                    Some(existing) => append(existing, register),
+                    None => register,
+                };
+                self.named.insert(name, register.clone());
+                self.unnamed = Some(register);
+                return result;
+            }
+            Some('0') => self.last_yank = Some(register.clone()),
+            Some(c) if c.is_ascii_digit() => {
+                let index = c.to_digit(10).unwrap() as usize - 1;
+                self.deletes[index] = Some(register.clone());
+            }
+            Some(c @ '+') | Some(c @ '*') => {
+                let copied = self.clipboard().map(|clipboard| {
+                    selection::set_text(clipboard, c, register.text.clone()).is_ok()
+                });
+                if copied != Some(true) {
+                    result = Err("Can't copy to the system clipboard");
+                }
+            }
+            _ if !deleted => self.last_yank = Some(register.clone()),
+            _ if register.kind == RegisterKind::Linewise || register.text.contains('\n') => {
+                self.deletes.rotate_right(1);
+                self.deletes[0] = Some(register.clone());
+            }
+            _ => {
+                self.named.insert('-', register.clone());
+            }
+        }
+        self.unnamed = Some(register);
+        result
+    }
+
+    /// The contents of the register called `name`, or of `""` when there is
+    /// none.
+    pub fn get(&mut self, name: Option<char>) -> Option<Register> {
+        match name {
+            None | Some('"') => self.unnamed.clone(),
+            Some('0') => self.last_yank.clone(),
+            Some(c) if c.is_ascii_digit() => {
+                let index = c.to_digit(10).unwrap() as usize - 1;
+                self.deletes[index].clone()
+            }
+            Some(c @ '+') | Some(c @ '*') => {
+                let text = selection::get_text(self.clipboard()?, c).ok()?;
+                let kind = if text.ends_with('\n') {
+                    RegisterKind::Linewise
+                } else {
+                    RegisterKind::Charwise
+                };
+                Some(Register { text, kind })
+            }
+            Some(c) => self.named.get(&c.to_ascii_lowercase()).cloned(),
+        }
+    }
+
+    /// Connects to the system clipboard the first time it is used.
+    fn clipboard(&mut self) -> Option<&mut Clipboard> {
+        if self.clipboard.is_none() {
+            self.clipboard = Clipboard::new().ok();
+        }
+        self.clipboard.as_mut()
+    }
+}
+
+/// Adds `extra` to the end of `existing`. If either one is linewise the
+/// result is too, like in vim.
+fn append(existing: Register, extra: Register) -> Register {
+    if existing.kind == RegisterKind::Linewise || extra.kind == RegisterKind::Linewise {
+        let mut text = existing.text;
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&extra.text);
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Register {
+            text,
+            kind: RegisterKind::Linewise,
+        }
+    } else {
+        Register {
+            text: existing.text + &extra.text,
+            kind: RegisterKind::Charwise,
+        }
+    }
+}
+
+/// Reads and writes `"+` on the system clipboard and `"*` on the primary
+/// selection that X11 has.
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+))]
+mod selection {
+    use arboard::{Clipboard, ClipboardExtLinux, Error, LinuxClipboardKind};
+
+    fn kind(name: char) -> LinuxClipboardKind {
+        if name == '*' {
+            LinuxClipboardKind::Primary
+        } else {
+            LinuxClipboardKind::Clipboard
+        }
+    }
+
+    pub fn get_text(clipboard: &mut Clipboard, name: char) -> Result<String, Error> {
+        clipboard.get_text_with_clipboard(kind(name))
+    }
+
+    pub fn set_text(clipboard: &mut Clipboard, name: char, text: String) -> Result<(), Error> {
+        clipboard.set_text_with_clipboard(text, kind(name))
+    }
+}
+
+/// Elsewhere there's no primary selection, so `"*` is the clipboard too.
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+mod selection {
+    use arboard::{Clipboard, Error};
+
+    pub fn get_text(clipboard: &mut Clipboard, _name: char) -> Result<String, Error> {
+        clipboard.get_text()
+    }
+
+    pub fn set_text(clipboard: &mut Clipboard, _name: char, text: String) -> Result<(), Error> {
+        clipboard.set_text(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Register {
+        Register {
+            text: text.to_owned(),
+            kind: RegisterKind::Charwise,
+        }
+    }
+
+    fn lines(text: &str) -> Register {
+        Register {
+            text: text.to_owned(),
+            kind: RegisterKind::Linewise,
+        }
+    }
+
+    fn text(registers: &mut Registers, name: char) -> Option<String> {
+        registers.get(Some(name)).map(|register| register.text)
+    }
+
+    #[test]
+    fn deleted_lines_shift_through_the_numbered_registers() {
+        let mut registers = Registers::new();
+        for i in 1..=10 {
+            let deleted = lines(&format!("{}\n", i));
+            registers.store(None, deleted, true).unwrap();
+        }
+        assert_eq!(text(&mut registers, '1').as_deref(), Some("10\n"));
+        assert_eq!(text(&mut registers, '9').as_deref(), Some("2\n"));
+        assert_eq!(text(&mut registers, '"').as_deref(), Some("10\n"));
+        // Small deletes go to `"-` and leave the numbered ones alone.
+        registers.store(None, chars("x"), true).unwrap();
+        assert_eq!(text(&mut registers, '-').as_deref(), Some("x"));
+        assert_eq!(text(&mut registers, '1').as_deref(), Some("10\n"));
+        assert_eq!(text(&mut registers, '0'), None);
+    }
+
+    #[test]
+    fn writing_a_numbered_register_sets_only_that_one() {
+        let mut registers = Registers::new();
+        registers.store(Some('5'), chars("five"), false).unwrap();
+        assert_eq!(text(&mut registers, '5').as_deref(), Some("five"));
+        for name in "1234".chars() {
+            assert_eq!(text(&mut registers, name), None);
+        }
+        registers.store(None, lines("a\n"), true).unwrap();
+        assert_eq!(text(&mut registers, '1').as_deref(), Some("a\n"));
+        assert_eq!(text(&mut registers, '6').as_deref(), Some("five"));
+        assert_eq!(text(&mut registers, '5'), None);
+    }
+
+    #[test]
+    fn yanks_go_to_register_0() {
+        let mut registers = Registers::new();
+        registers.store(None, chars("yanked"), false).unwrap();
+        registers.store(None, lines("deleted\n"), true).unwrap();
+        assert_eq!(text(&mut registers, '0').as_deref(), Some("yanked"));
+        assert_eq!(text(&mut registers, '"').as_deref(), Some("deleted\n"));
+        registers.store(Some('0'), chars("set"), false).unwrap();
+        assert_eq!(text(&mut registers, '0').as_deref(), Some("set"));
+    }
+
+    #[test]
+    fn uppercase_names_append() {
+        let mut registers = Registers::new();
+        registers.store(Some('a'), chars("foo"), false).unwrap();
+        registers.store(Some('A'), chars("bar"), false).unwrap();
+        assert_eq!(text(&mut registers, 'a').as_deref(), Some("foobar"));
+        assert_eq!(text(&mut registers, 'A').as_deref(), Some("foobar"));
+        assert_eq!(text(&mut registers, '"').as_deref(), Some("foobar"));
+        registers.store(Some('A'), lines("baz\n"), false).unwrap();
+        let register = registers.get(Some('a')).unwrap();
+        assert_eq!(register.text, "foobar\nbaz\n");
+        assert_eq!(register.kind, RegisterKind::Linewise);
+        registers.store(Some('B'), chars("new"), false).unwrap();
+        assert_eq!(text(&mut registers, 'b').as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn the_black_hole_register_keeps_nothing() {
+        let mut registers = Registers::new();
+        registers.store(None, chars("kept"), false).unwrap();
+        registers
+            .store(Some('_'), lines("dropped\n"), true)
+            .unwrap();
+        assert_eq!(text(&mut registers, '"').as_deref(), Some("kept"));
+        assert_eq!(text(&mut registers, '1'), None);
+        assert_eq!(text(&mut registers, '_'), None);
+    }
+}
@@ -0,0 +1,257 @@
+use super::range::Position;
+
+/// The part of a buffer a view shows.
+#[derive(Clone)]
+pub struct Scroll {
+    /// The first line on screen.
+    pub top: usize,
+    /// How many rows fit on screen.
+    pub rows: usize,
+    /// How many screen columns fit across.
+    pub columns: usize,
+    /// The first screen column on screen when lines don't wrap.
+    pub left: usize,
+    /// How many lines to keep in sight above and below the cursor.
+    pub scrolloff: usize,
+    /// How many columns to keep in sight left and right of the cursor.
+    pub sidescrolloff: usize,
+    /// Whether lines too long for the screen go on as many rows as they
+    /// need.
+    pub wrap: bool,
+}
+
+impl Scroll {
+    pub fn new(scrolloff: usize, sidescrolloff: usize, wrap: bool) -> Scroll {
+        Scroll {
+            top: 0,
+            rows: 0,
+            columns: 0,
+            left: 0,
+            scrolloff,
+            sidescrolloff,
+            wrap,
+        }
+    }
+
+    /// How many screen columns a wrapped row takes up, leaving the last one
+    /// for the wrap indicator.
+    pub fn wrap_width(&self) -> usize {
+        self.columns.saturating_sub(1).max(1)
+    }
+
+    /// Scrolls just enough to show `line` with `scrolloff` lines around it,
+    /// or as many as fit, where each line takes `height` rows. Nothing is
+    /// kept in sight past the last of the buffer's `lines`.
+    pub fn keep_visible(&mut self, line: usize, lines: usize, height: impl Fn(usize) -> usize) {
+        if self.rows == 0 {
+            return;
+        }
+        let margin = self.scrolloff.min((self.rows - 1) / 2);
+        if line < self.top + margin {
+            self.top = line.saturating_sub(margin);
+            return;
+        }
+        // The highest top that still fits everything down to `last`, but
+        // never so high `line` itself would be off screen.
+        let last = (line + margin).min(lines.saturating_sub(1)).max(line);
+        let mut top = last;
+        let mut used = height(last);
+        while top > 0 {
+            let above = height(top - 1);
+            if used + above > self.rows {
+                break;
+            }
+            used += above;
+            top -= 1;
+        }
+        if self.top < top {
+            self.top = top.min(line);
+        }
+    }
+
+    /// How many columns are kept in sight left and right of the cursor,
+    /// which is `sidescrolloff` or as many as fit.
+    pub fn side_margin(&self) -> usize {
+        self.sidescrolloff.min(self.columns.saturating_sub(1) / 2)
+    }
+
+    /// Scrolls sideways just enough to show the screen columns from `x` to
+    /// `x + width` with `sidescrolloff` columns around them. Wrapped lines
+    /// are always shown from their start.
+    pub fn keep_column_visible(&mut self, x: usize, width: usize) {
+        if self.wrap {
+            self.left = 0;
+            return;
+        }
+        if self.columns == 0 {
+            return;
+        }
+        let margin = self.side_margin();
+        if x < self.left + margin {
+            self.left = x.saturating_sub(margin);
+        } else if x + width + margin > self.left + self.columns {
+            self.left = x + width + margin - self.columns;
+        }
+    }
+}
+
+/// A row on screen, showing the part of `line` from `start` up to `end`.
+/// A line that doesn't wrap is a single row.
+#[derive(Copy, Clone, Debug)]
+pub struct Row {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    /// The screen column `start` is drawn at, counting from the start of
+    /// the line.
+    pub x: usize,
+    /// Whether this is the last row of its line.
+    pub last: bool,
+}
+
+impl Row {
+    /// Whether `pos` is drawn on this row. Positions past the end of the
+    /// line go on its last row.
+    pub fn contains(&self, pos: Position) -> bool {
+        pos.line == self.line && pos.col >= self.start && (pos.col < self.end || self.last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A view of `rows` rows and 80 columns scrolled to `top`.
+    fn scroll(rows: usize, scrolloff: usize, top: usize) -> Scroll {
+        let mut scroll = Scroll::new(scrolloff, 0, false);
+        scroll.rows = rows;
+        scroll.columns = 80;
+        scroll.top = top;
+        scroll
+    }
+
+    /// Where the view scrolls to show `line` of 100 lines a row each.
+    fn top_for(mut scroll: Scroll, line: usize) -> usize {
+        scroll.keep_visible(line, 100, |_| 1);
+        scroll.top
+    }
+
+    #[test]
+    fn scrolloff_keeps_lines_in_sight() {
+        assert_eq!(top_for(scroll(10, 3, 0), 5), 0);
+        assert_eq!(top_for(scroll(10, 3, 0), 6), 0);
+        assert_eq!(top_for(scroll(10, 3, 0), 7), 1);
+        assert_eq!(top_for(scroll(10, 3, 0), 50), 44);
+        assert_eq!(top_for(scroll(10, 3, 50), 53), 50);
+        assert_eq!(top_for(scroll(10, 3, 50), 52), 49);
+        assert_eq!(top_for(scroll(10, 0, 50), 50), 50);
+        assert_eq!(top_for(scroll(10, 0, 50), 49), 49);
+    }
+
+    #[test]
+    fn scrolloff_stops_at_the_ends_of_the_buffer() {
+        assert_eq!(top_for(scroll(10, 3, 5), 1), 0);
+        assert_eq!(top_for(scroll(10, 3, 5), 0), 0);
+        assert_eq!(top_for(scroll(10, 3, 0), 99), 90);
+        assert_eq!(top_for(scroll(10, 3, 0), 97), 90);
+        assert_eq!(top_for(scroll(10, 3, 0), 95), 89);
+        let mut short = scroll(10, 3, 0);
+        short.keep_visible(2, 3, |_| 1);
+        assert_eq!(short.top, 0);
+    }
+
+    #[test]
+    fn scrolloff_is_cut_down_to_fit_small_views() {
+        // Half the view, less the cursor line.
+        assert_eq!(top_for(scroll(4, 3, 0), 10), 8);
+        assert_eq!(top_for(scroll(4, 3, 20), 20), 19);
+        assert_eq!(top_for(scroll(1, 3, 0), 10), 10);
+        assert_eq!(top_for(scroll(0, 3, 7), 50), 7);
+    }
+
+    #[test]
+    fn wrapped_lines_take_as_many_rows_as_they_need() {
+        let height = |line: usize| if line == 5 { 3 } else { 1 };
+        let mut wrapped = scroll(10, 0, 0);
+        wrapped.keep_visible(12, 100, height);
+        assert_eq!(wrapped.top, 5);
+        wrapped.keep_visible(13, 100, height);
+        assert_eq!(wrapped.top, 6);
+        // A line taller than the view goes at the top.
+        let mut tall = scroll(10, 3, 0);
+        tall.keep_visible(5, 100, |line| if line == 5 { 20 } else { 1 });
+        assert_eq!(tall.top, 5);
+    }
+
+    #[test]
+    fn rows_hold_their_part_of_the_line() {
+        let first = Row {
+            line: 3,
+            start: 0,
+            end: 4,
+            x: 0,
+            last: false,
+        };
+        let last = Row {
+            line: 3,
+            start: 4,
+            end: 6,
+            x: 4,
+            last: true,
+        };
+        assert!(first.contains(Position::new(3, 0)));
+        assert!(first.contains(Position::new(3, 3)));
+        assert!(!first.contains(Position::new(3, 4)));
+        assert!(last.contains(Position::new(3, 4)));
+        assert!(last.contains(Position::new(3, 6)));
+        assert!(last.contains(Position::new(3, 9)));
+        assert!(!last.contains(Position::new(3, 3)));
+        assert!(!last.contains(Position::new(4, 5)));
+    }
+
+    /// Where a view 20 columns wide with `left` at the left edge scrolls
+    /// sideways to show `width` columns at `x`.
+    fn left_for(sidescrolloff: usize, left: usize, x: usize, width: usize) -> usize {
+        let mut scroll = Scroll::new(0, sidescrolloff, false);
+        scroll.columns = 20;
+        scroll.left = left;
+        scroll.keep_column_visible(x, width);
+        scroll.left
+    }
+
+    #[test]
+    fn sidescrolloff_keeps_columns_in_sight() {
+        assert_eq!(left_for(5, 0, 14, 1), 0);
+        assert_eq!(left_for(5, 0, 15, 1), 1);
+        assert_eq!(left_for(5, 0, 100, 1), 86);
+        assert_eq!(left_for(5, 86, 95, 1), 86);
+        assert_eq!(left_for(5, 86, 90, 1), 85);
+        assert_eq!(left_for(5, 10, 2, 1), 0);
+        assert_eq!(left_for(0, 0, 19, 1), 0);
+        assert_eq!(left_for(0, 0, 20, 1), 1);
+        // The whole of a wide char, and the margin past it.
+        assert_eq!(left_for(5, 0, 14, 2), 1);
+    }
+
+    #[test]
+    fn sidescrolloff_is_cut_down_to_fit_narrow_views() {
+        let mut scroll = Scroll::new(0, 50, false);
+        scroll.columns = 7;
+        assert_eq!(scroll.side_margin(), 3);
+        scroll.keep_column_visible(10, 1);
+        assert_eq!(scroll.left, 7);
+        scroll.columns = 0;
+        scroll.keep_column_visible(100, 1);
+        assert_eq!(scroll.left, 7);
+    }
+
+    #[test]
+    fn wrapped_lines_are_shown_from_their_start() {
+        let mut scroll = Scroll::new(0, 5, true);
+        scroll.columns = 20;
+        scroll.left = 30;
+        scroll.keep_column_visible(100, 1);
+        assert_eq!(scroll.left, 0);
+        assert_eq!(scroll.wrap_width(), 19);
+    }
+}
@@ -0,0 +1,325 @@
+extern crate regex;
+extern crate regex_syntax;
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind};
+
+use super::buffer::Buffer;
+use super::error::ExError;
+use super::range::{Position, Range};
+
+/// Which way `/` (forward) or `?` (backward) looks for a match.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+
+    /// The command line prompt for a search this way.
+    pub fn prompt(self) -> char {
+        match self {
+            Direction::Forward => '/',
+            Direction::Backward => '?',
+        }
+    }
+}
+
+/// Compiles `pattern` as a regular expression. Case is ignored, or not, as
+/// `ignore_case` says, and otherwise unless the pattern has an uppercase
+/// letter that isn't part of an escape like `\S`. A pattern with a line
+/// break in it is refused rather than never matching.
+pub fn compile(pattern: &str, ignore_case: Option<bool>) -> Result<Regex, ExError> {
+    let hir =
+        regex_syntax::parse(pattern).map_err(|_| ExError::InvalidPattern(pattern.to_owned()))?;
+    if has_line_break(&hir) {
+        return Err(ExError::MultiLinePattern(pattern.to_owned()));
+    }
+    let ignore_case = ignore_case.unwrap_or_else(|| !has_uppercase(pattern));
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|_| ExError::InvalidPattern(pattern.to_owned()))
+}
+
+/// Whether `hir` matches a line break of its own, like `\n` or `[\n]`,
+/// rather than through a class that happens to hold one, like `\s`.
+fn has_line_break(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+        HirKind::Repetition(repetition) => has_line_break(&repetition.sub),
+        HirKind::Capture(capture) => has_line_break(&capture.sub),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(has_line_break),
+        HirKind::Empty | HirKind::Class(_) | HirKind::Look(_) => false,
+    }
+}
+
+fn has_uppercase(pattern: &str) -> bool {
+    let mut escaped = false;
+    for c in pattern.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/// The char offset of byte `byte` in `text`.
+pub fn char_col(text: &str, byte: usize) -> usize {
+    text[..byte].chars().count()
+}
+
+/// The byte offset of char `col` in `text`, or its length past the end.
+pub fn byte_offset(text: &str, col: usize) -> usize {
+    text.char_indices()
+        .nth(col)
+        .map_or(text.len(), |(byte, _)| byte)
+}
+
+/// A search as typed, kept so `n` and `N` can repeat it. Matches don't
+/// span lines, so each line is searched on its own, straight from the
+/// buffer's rope, and patterns with a line break are refused.
+#[derive(Clone, Debug)]
+pub struct Search {
+    pub text: String,
+    pub direction: Direction,
+    regex: Regex,
+}
+
+impl Search {
+    pub fn new(text: &str, direction: Direction) -> Result<Search, ExError> {
+        Ok(Search {
+            text: text.to_owned(),
+            direction,
+            regex: compile(text, None)?,
+        })
+    }
+
+    /// A search for `word` as a whole word, as `*` and `#` make.
+    pub fn word(word: &str, direction: Direction) -> Search {
+        let text = format!(r"\<{}\>", regex::escape(word));
+        Search::new(&text, direction).expect("an escaped word is a valid pattern")
+    }
+
+    /// How the search shows in the command line, like `/foo`.
+    pub fn describe(&self) -> String {
+        format!("{}{}", self.direction.prompt(), self.text)
+    }
+
+    /// The first match starting after `from` going forward, or the last
+    /// one starting before it going backward, wrapping around the end of
+    /// the buffer. Says too whether it wrapped.
+    pub fn find(
+        &self,
+        buffer: &Buffer,
+        from: Position,
+        direction: Direction,
+    ) -> Option<(Range, bool)> {
+        let lines = buffer.get_lines_count();
+        // Every line from the one `from` is on, and that one again at the
+        // end for the part of it on the other side of `from`.
+        for i in 0..=lines {
+            let (line, wrapped) = match direction {
+                Direction::Forward => ((from.line + i) % lines, from.line + i >= lines),
+                Direction::Backward => ((from.line + lines - i % lines) % lines, i > from.line),
+            };
+            let found = match (direction, i) {
+                (Direction::Forward, 0) => self.match_after(buffer, from),
+                (Direction::Forward, _) => self
+                    .line_matches(buffer, line)
+                    .into_iter()
+                    .find(|found| i < lines || found.start.col <= from.col),
+                (Direction::Backward, _) => {
+                    self.line_matches(buffer, line)
+                        .into_iter()
+                        .rev()
+                        .find(|found| match i {
+                            0 => found.start.col < from.col,
+                            i if i == lines => found.start.col >= from.col,
+                            _ => true,
+                        })
+                }
+            };
+            if let Some(found) = found {
+                return Some((found, wrapped));
+            }
+        }
+        None
+    }
+
+    /// Every match on lines `first` to `last`, not overlapping each other.
+    pub fn matches_in(&self, buffer: &Buffer, first: usize, last: usize) -> Vec<Range> {
+        (first..=last)
+            .flat_map(|line| self.line_matches(buffer, line))
+            .collect()
+    }
+
+    /// The first match on the line of `from` that starts after it.
+    fn match_after(&self, buffer: &Buffer, from: Position) -> Option<Range> {
+        let text = buffer.line_str(from.line);
+        let start = from.col + 1;
+        if start > text.chars().count() {
+            return None;
+        }
+        let found = self.regex.find_at(&text, byte_offset(&text, start))?;
+        Some(Range::new(
+            Position::new(from.line, char_col(&text, found.start())),
+            Position::new(from.line, char_col(&text, found.end())),
+        ))
+    }
+
+    fn line_matches(&self, buffer: &Buffer, line: usize) -> Vec<Range> {
+        let text = buffer.line_str(line);
+        self.regex
+            .find_iter(&text)
+            .map(|found| {
+                Range::new(
+                    Position::new(line, char_col(&text, found.start())),
+                    Position::new(line, char_col(&text, found.end())),
+                )
+            })
+            .collect()
+    }
+}
+
+/// A search being typed into the command line.
+pub struct Incsearch {
+    pub direction: Direction,
+    /// Where the cursor was before it.
+    pub origin: Position,
+    /// What's typed so far, unless it isn't a valid pattern yet.
+    pub search: Option<Search>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        Buffer::from_reader(text.as_bytes()).unwrap()
+    }
+
+    /// Where `search` finds a match from `line`, `col`, as its line, start
+    /// and end columns and whether it wrapped.
+    fn find(
+        search: &Search,
+        buffer: &Buffer,
+        (line, col): (usize, usize),
+        direction: Direction,
+    ) -> Option<(usize, usize, usize, bool)> {
+        search
+            .find(buffer, Position::new(line, col), direction)
+            .map(|(found, wrapped)| (found.start.line, found.start.col, found.end.col, wrapped))
+    }
+
+    #[test]
+    fn finds_forward_and_backward_with_wrapping() {
+        let buffer = buffer("foo bar foo\nbaz Foo\nfoobar\n");
+        let search = Search::new("foo", Direction::Forward).unwrap();
+        let forward = Direction::Forward;
+        let backward = Direction::Backward;
+        assert_eq!(
+            find(&search, &buffer, (0, 0), forward),
+            Some((0, 8, 11, false))
+        );
+        assert_eq!(
+            find(&search, &buffer, (0, 8), forward),
+            Some((1, 4, 7, false))
+        );
+        assert_eq!(
+            find(&search, &buffer, (2, 0), forward),
+            Some((0, 0, 3, true))
+        );
+        assert_eq!(
+            find(&search, &buffer, (0, 0), backward),
+            Some((2, 0, 3, true))
+        );
+        assert_eq!(
+            find(&search, &buffer, (1, 4), backward),
+            Some((0, 8, 11, false))
+        );
+        assert_eq!(
+            find(&search, &buffer, (0, 8), backward),
+            Some((0, 0, 3, false))
+        );
+    }
+
+    #[test]
+    fn a_single_match_is_found_again_by_wrapping() {
+        let buffer = buffer("foo");
+        let search = Search::new("foo", Direction::Forward).unwrap();
+        assert_eq!(
+            find(&search, &buffer, (0, 0), Direction::Forward),
+            Some((0, 0, 3, true))
+        );
+        assert_eq!(
+            find(&search, &buffer, (0, 0), Direction::Backward),
+            Some((0, 0, 3, true))
+        );
+        let search = Search::new("bar", Direction::Forward).unwrap();
+        assert_eq!(find(&search, &buffer, (0, 0), Direction::Forward), None);
+    }
+
+    #[test]
+    fn case_is_ignored_without_uppercase() {
+        let buffer = buffer("Foo foo");
+        let matches = |text| {
+            Search::new(text, Direction::Forward)
+                .unwrap()
+                .matches_in(&buffer, 0, 0)
+                .len()
+        };
+        assert_eq!(matches("foo"), 2);
+        assert_eq!(matches("Foo"), 1);
+        assert_eq!(matches(r"\sfoo"), 1);
+        assert_eq!(matches("(?-i)foo"), 1);
+    }
+
+    #[test]
+    fn words_match_whole() {
+        let buffer = buffer("foo foobar foo_bar (foo)");
+        let search = Search::word("foo", Direction::Forward);
+        let cols: Vec<usize> = search
+            .matches_in(&buffer, 0, 0)
+            .iter()
+            .map(|found| found.start.col)
+            .collect();
+        assert_eq!(cols, vec![0, 20]);
+    }
+
+    #[test]
+    fn columns_count_chars_not_bytes() {
+        let buffer = buffer("héllo wörld");
+        let search = Search::new("w.r", Direction::Forward).unwrap();
+        assert_eq!(
+            find(&search, &buffer, (0, 0), Direction::Forward),
+            Some((0, 6, 9, false))
+        );
+    }
+
+    #[test]
+    fn refuses_bad_and_multi_line_patterns() {
+        assert!(matches!(
+            Search::new("foo(", Direction::Forward),
+            Err(ExError::InvalidPattern(_))
+        ));
+        for pattern in &[r"o\nf", "o\nf", r"o[\n]f", r"(a\n|b)+"] {
+            assert!(matches!(
+                Search::new(pattern, Direction::Forward),
+                Err(ExError::MultiLinePattern(_))
+            ));
+        }
+        assert!(Search::new(r"o\s", Direction::Forward).is_ok());
+        assert!(Search::new(r"[^a]", Direction::Forward).is_ok());
+    }
+}
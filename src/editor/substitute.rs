@@ -0,0 +1,332 @@
+extern crate regex;
+use regex::{Captures, Regex};
+
+use super::buffer::Buffer;
+use super::error::ExError;
+use super::range::{Position, Range};
+use super::search::{byte_offset, char_col};
+
+/// What `:s/pattern/replacement/flags` asks for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Substitute {
+    /// Empty to use the last search.
+    pub pattern: String,
+    pub replacement: String,
+    /// `g`: every match on a line rather than only the first.
+    pub global: bool,
+    /// `c`: asks before replacing each match.
+    pub confirm: bool,
+    /// `i` or `I`: ignores case, or doesn't, whatever the pattern has.
+    pub ignore_case: Option<bool>,
+}
+
+impl Substitute {
+    /// Splits the argument of `:s`. The first char, anything but a letter,
+    /// digit, space, `\`, `"` or `|`, separates the pattern, the
+    /// replacement and the flags, and stands for itself after a `\`. The
+    /// replacement and the flags can be left out.
+    pub fn parse(argument: &str) -> Result<Substitute, ExError> {
+        let mut chars = argument.chars();
+        let delimiter = match chars.next() {
+            Some(c) if !(c.is_alphanumeric() || c.is_whitespace() || "\\\"|".contains(c)) => c,
+            _ => return Err(ExError::InvalidArgument(argument.to_owned())),
+        };
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            if c == delimiter && parts.len() < 3 {
+                parts.push(String::new());
+                continue;
+            }
+            let part = parts.last_mut().unwrap();
+            match c {
+                '\\' => match chars.next() {
+                    Some(next) if next == delimiter => part.push(next),
+                    Some(next) => {
+                        part.push('\\');
+                        part.push(next);
+                    }
+                    None => part.push('\\'),
+                },
+                c => part.push(c),
+            }
+        }
+        let mut parts = parts.into_iter();
+        let mut substitute = Substitute {
+            pattern: parts.next().unwrap_or_default(),
+            replacement: parts.next().unwrap_or_default(),
+            global: false,
+            confirm: false,
+            ignore_case: None,
+        };
+        let flags = parts.next().unwrap_or_default();
+        for flag in flags.chars() {
+            match flag {
+                'g' => substitute.global = true,
+                'c' => substitute.confirm = true,
+                'i' => substitute.ignore_case = Some(true),
+                'I' => substitute.ignore_case = Some(false),
+                _ => return Err(ExError::TrailingCharacters(flags)),
+            }
+        }
+        Ok(substitute)
+    }
+}
+
+/// The text a match is replaced with: `replacement` with `&` and `\0` put
+/// back as the whole match, `\1` to `\9` as its groups, `\r` and `\n` as
+/// line breaks and `\t` as a tab. Any other char after a `\` stands for
+/// itself.
+pub fn expand(replacement: &str, captures: &Captures) -> String {
+    let group = |i: usize| captures.get(i).map_or("", |group| group.as_str());
+    let mut text = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => text.push_str(group(0)),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => text.push_str(group(digit as usize - '0' as usize)),
+                Some('r') | Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some(c) => text.push(c),
+                None => text.push('\\'),
+            },
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// A `:s` worked through one match at a time, so it can stop to ask about
+/// each one.
+pub struct Substitution {
+    regex: Regex,
+    replacement: String,
+    global: bool,
+    /// Where to look for the next match from.
+    next: Position,
+    /// The last line to look on, further down as line breaks are put in.
+    last: usize,
+    /// Where the last match taken ended, since an empty match there doesn't
+    /// count.
+    after: Option<Position>,
+    /// How many matches were found.
+    pub found: usize,
+    /// How many matches were replaced.
+    pub count: usize,
+    /// How many lines had a match replaced.
+    pub lines: usize,
+    /// The line the last replacement ended on.
+    pub last_line: Option<usize>,
+}
+
+impl Substitution {
+    /// Starts on lines `first` to `last`.
+    pub fn new(regex: Regex, substitute: &Substitute, first: usize, last: usize) -> Substitution {
+        Substitution {
+            regex,
+            replacement: substitute.replacement.clone(),
+            global: substitute.global,
+            next: Position::new(first, 0),
+            last,
+            after: None,
+            found: 0,
+            count: 0,
+            lines: 0,
+            last_line: None,
+        }
+    }
+
+    /// The next match and what it'd be replaced with, if any are left.
+    pub fn next_match(&mut self, buffer: &Buffer) -> Option<(Range, String)> {
+        while self.next.line <= self.last {
+            let line = self.next.line;
+            let text = buffer.line_str(line);
+            if self.next.col <= text.chars().count() {
+                let from = byte_offset(&text, self.next.col);
+                if let Some(captures) = self.regex.captures_at(&text, from) {
+                    let whole = captures.get(0).unwrap();
+                    let start = Position::new(line, char_col(&text, whole.start()));
+                    let end = Position::new(line, char_col(&text, whole.end()));
+                    if start == end && self.after == Some(start) {
+                        self.next.col = start.col + 1;
+                        continue;
+                    }
+                    self.found += 1;
+                    return Some((Range::new(start, end), expand(&self.replacement, &captures)));
+                }
+            }
+            self.next = Position::new(line + 1, 0);
+        }
+        None
+    }
+
+    /// Replaces `found` with `text` and moves on past it.
+    pub fn replace(&mut self, buffer: &mut Buffer, found: Range, text: &str) {
+        let change = buffer.replace(found, text);
+        self.count += 1;
+        if self.last_line != Some(found.start.line) {
+            self.lines += 1;
+        }
+        self.last_line = Some(change.new_end.line);
+        self.last += change.new_end.line - change.old_end.line;
+        self.move_past(change.new_end);
+    }
+
+    /// Leaves `found` as it is and moves on past it.
+    pub fn skip(&mut self, found: Range) {
+        self.move_past(found.end);
+    }
+
+    fn move_past(&mut self, end: Position) {
+        self.after = Some(end);
+        self.next = if self.global {
+            end
+        } else {
+            Position::new(end.line + 1, 0)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::search::compile;
+
+    /// Runs `:s` with `argument` on lines `first` to `last` of `text`,
+    /// giving the text after, how many substitutions on how many lines,
+    /// and checking one undo puts it all back.
+    fn substitute(text: &str, argument: &str, first: usize, last: usize) -> (String, usize, usize) {
+        let mut buffer = Buffer::from_reader(text.as_bytes()).unwrap();
+        let substitute = Substitute::parse(argument).unwrap();
+        let regex = compile(&substitute.pattern, substitute.ignore_case).unwrap();
+        let mut substitution = Substitution::new(regex, &substitute, first, last);
+        buffer.begin_undo_group(Position::new(0, 0));
+        while let Some((found, text)) = substitution.next_match(&buffer) {
+            substitution.replace(&mut buffer, found, &text);
+        }
+        buffer.end_undo_group();
+        let after = contents(&buffer);
+        buffer.undo();
+        assert_eq!(contents(&buffer), text);
+        (after, substitution.count, substitution.lines)
+    }
+
+    fn contents(buffer: &Buffer) -> String {
+        (0..buffer.get_lines_count())
+            .map(|line| buffer.get_line_at(line))
+            .collect()
+    }
+
+    #[test]
+    fn parses_pattern_replacement_and_flags() {
+        let parsed = Substitute::parse("/a/b/gcI").unwrap();
+        assert_eq!(
+            parsed,
+            Substitute {
+                pattern: "a".to_owned(),
+                replacement: "b".to_owned(),
+                global: true,
+                confirm: true,
+                ignore_case: Some(false),
+            }
+        );
+        let parsed = Substitute::parse("/a").unwrap();
+        assert_eq!(
+            (parsed.pattern.as_str(), parsed.replacement.as_str()),
+            ("a", "")
+        );
+        assert!(!parsed.global);
+        assert_eq!(Substitute::parse("//x/i").unwrap().ignore_case, Some(true));
+    }
+
+    #[test]
+    fn escaped_delimiters_stand_for_themselves() {
+        let parsed = Substitute::parse(r"/a\/b/c\/d\1/").unwrap();
+        assert_eq!(parsed.pattern, "a/b");
+        assert_eq!(parsed.replacement, r"c/d\1");
+        let parsed = Substitute::parse("#a/b#c#g").unwrap();
+        assert_eq!((parsed.pattern.as_str(), parsed.global), ("a/b", true));
+    }
+
+    #[test]
+    fn refuses_bad_delimiters_and_flags() {
+        assert!(matches!(
+            Substitute::parse("abc"),
+            Err(ExError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Substitute::parse(r"\a\b\"),
+            Err(ExError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Substitute::parse("/a/b/gz"),
+            Err(ExError::TrailingCharacters(_))
+        ));
+        assert!(matches!(
+            Substitute::parse("/a/b/g/"),
+            Err(ExError::TrailingCharacters(_))
+        ));
+    }
+
+    #[test]
+    fn expands_matches_groups_and_escapes() {
+        let regex = Regex::new(r"(\w+) (\w+)(x)?").unwrap();
+        let captures = regex.captures("john smith").unwrap();
+        assert_eq!(expand(r"\2, \1", &captures), "smith, john");
+        assert_eq!(expand(r"[&] [\0]", &captures), "[john smith] [john smith]");
+        assert_eq!(expand(r"<\3>", &captures), "<>");
+        assert_eq!(expand(r"\&\\", &captures), "&\\");
+        assert_eq!(expand(r"\1\r\2\n\t", &captures), "john\nsmith\n\t");
+    }
+
+    #[test]
+    fn replaces_the_first_or_every_match() {
+        let text = "foo bar\nfoo foo\n";
+        assert_eq!(
+            substitute(text, "/foo/X/", 0, 1),
+            ("X bar\nX foo\n".to_owned(), 2, 2)
+        );
+        assert_eq!(
+            substitute(text, "/foo/X/g", 0, 1),
+            ("X bar\nX X\n".to_owned(), 3, 2)
+        );
+        assert_eq!(
+            substitute(text, "/foo/X/g", 1, 1),
+            ("foo bar\nX X\n".to_owned(), 2, 1)
+        );
+        assert_eq!(
+            substitute(text, "/(o+)/<\\1>/", 0, 0).0,
+            "f<oo> bar\nfoo foo\n"
+        );
+    }
+
+    #[test]
+    fn empty_matches_are_taken_once_each() {
+        assert_eq!(substitute("abc\n", "/x*/-/g", 0, 0).0, "-a-b-c-\n");
+        assert_eq!(substitute("baaac\n", "/a*/-/g", 0, 0).0, "-b-c-\n");
+        assert_eq!(substitute("ab\ncd\n", "/^/> /", 0, 1).0, "> ab\n> cd\n");
+    }
+
+    #[test]
+    fn line_breaks_put_in_move_the_range_down() {
+        let (text, count, lines) = substitute("a,b\nc,d\ne,f\n", "/,/\\r/g", 0, 1);
+        assert_eq!(text, "a\nb\nc\nd\ne,f\n");
+        assert_eq!((count, lines), (2, 2));
+    }
+
+    #[test]
+    fn skipped_matches_are_left_alone() {
+        let mut buffer = Buffer::from_reader("a a a".as_bytes()).unwrap();
+        let substitute = Substitute::parse("/a/b/g").unwrap();
+        let regex = compile(&substitute.pattern, None).unwrap();
+        let mut substitution = Substitution::new(regex, &substitute, 0, 0);
+        let (found, text) = substitution.next_match(&buffer).unwrap();
+        substitution.skip(found);
+        let (found, _) = substitution.next_match(&buffer).unwrap();
+        assert_eq!(found.start.col, 2);
+        substitution.replace(&mut buffer, found, &text);
+        assert!(substitution.next_match(&buffer).is_some());
+        assert_eq!(buffer.line_text(0), "a b a");
+        assert_eq!((substitution.found, substitution.count), (3, 1));
+    }
+}
@@ -0,0 +1,302 @@
+use super::buffer::Buffer;
+use super::range::{Position, Range, Span};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ObjectKind {
+    /// `w`, or `W` when true.
+    Word(bool),
+    /// Text between a pair of quote chars on the same line.
+    Quote(char),
+    /// Text between a pair of brackets, which may span lines.
+    Block(char, char),
+    Paragraph,
+}
+
+/// A text object such as `iw` or `a(`: `inner` leaves out the surrounding
+/// whitespace or delimiters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextObject {
+    pub kind: ObjectKind,
+    pub inner: bool,
+}
+
+impl TextObject {
+    /// Resolves the object char typed after `i` or `a`.
+    pub fn from_char(c: char, inner: bool) -> Option<TextObject> {
+        let kind = match c {
+            'w' => ObjectKind::Word(false),
+            'W' => ObjectKind::Word(true),
+            '"' | '\'' | '`' => ObjectKind::Quote(c),
+            '(' | ')' | 'b' => ObjectKind::Block('(', ')'),
+            '{' | '}' | 'B' => ObjectKind::Block('{', '}'),
+            '[' | ']' => ObjectKind::Block('[', ']'),
+            '<' | '>' => ObjectKind::Block('<', '>'),
+            'p' => ObjectKind::Paragraph,
+            _ => return None,
+        };
+        Some(TextObject { kind, inner })
+    }
+
+    /// The text the object covers around `pos`, if there is any.
+    pub fn select(self, buffer: &Buffer, pos: Position) -> Option<Span> {
+        match self.kind {
+            ObjectKind::Word(big) => Some(Span::Chars(word(buffer, pos, big, self.inner))),
+            ObjectKind::Quote(quote) => quoted(buffer, pos, quote, self.inner).map(Span::Chars),
+            ObjectKind::Block(open, close) => {
+                block(buffer, pos, open, close, self.inner).map(Span::Chars)
+            }
+            ObjectKind::Paragraph => Some(paragraph(buffer, pos.line, self.inner)),
+        }
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn same_class(a: char, b: char, big: bool) -> bool {
+    let keyword = |c: char| c.is_alphanumeric() || c == '_';
+    if is_blank(a) || is_blank(b) {
+        is_blank(a) && is_blank(b)
+    } else {
+        big || keyword(a) == keyword(b)
+    }
+}
+
+/// `iw` is the run of chars of the same class under the cursor; `aw` adds
+/// the blanks after it, or before it if there are none after.
+fn word(buffer: &Buffer, pos: Position, big: bool, inner: bool) -> Range {
+    let line: Vec<char> = buffer
+        .get_line_at(pos.line)
+        .chars()
+        .take(buffer.line_len(pos.line))
+        .collect();
+    if line.is_empty() {
+        return Range::new(pos, pos);
+    }
+    let col = pos.col.min(line.len() - 1);
+    let c = line[col];
+    let mut start = col;
+    while start > 0 && same_class(line[start - 1], c, big) {
+        start -= 1;
+    }
+    let mut end = col + 1;
+    while end < line.len() && same_class(line[end], c, big) {
+        end += 1;
+    }
+    if !inner {
+        let trailing = end;
+        while end < line.len() && is_blank(line[end]) {
+            end += 1;
+        }
+        if end == trailing {
+            while start > 0 && is_blank(line[start - 1]) {
+                start -= 1;
+            }
+        }
+    }
+    Range::new(Position::new(pos.line, start), Position::new(pos.line, end))
+}
+
+/// The quoted string on the cursor line that contains the cursor, or the
+/// first one after it.
+fn quoted(buffer: &Buffer, pos: Position, quote: char, inner: bool) -> Option<Range> {
+    let line: Vec<char> = buffer.get_line_at(pos.line).chars().collect();
+    let quotes: Vec<usize> = line
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| **c == quote && (*i == 0 || line[i - 1] != '\\'))
+        .map(|(i, _)| i)
+        .collect();
+    let (open, close) = quotes
+        .chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, close)| *close >= pos.col)?;
+    let (start, end) = if inner {
+        (open + 1, close)
+    } else {
+        let mut end = close + 1;
+        while end < line.len() && is_blank(line[end]) {
+            end += 1;
+        }
+        (open, end)
+    };
+    Some(Range::new(
+        Position::new(pos.line, start),
+        Position::new(pos.line, end),
+    ))
+}
+
+/// The innermost `open`/`close` pair around the cursor. When the inside
+/// starts and ends with line breaks, as in most code blocks, the inner
+/// object covers just the lines in between.
+fn block(buffer: &Buffer, pos: Position, open: char, close: char, inner: bool) -> Option<Range> {
+    let cursor = buffer.pos_to_char(pos);
+    let len = buffer.len_chars();
+    if len == 0 {
+        return None;
+    }
+    let mut depth = 0;
+    let mut start = None;
+    let mut idx = cursor.min(len.saturating_sub(1)) + 1;
+    while idx > 0 {
+        idx -= 1;
+        let c = buffer.char_at(idx);
+        if c == close && idx != cursor {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                start = Some(idx);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let start = start?;
+    let mut depth = 0;
+    let mut end = None;
+    for idx in start + 1..len {
+        let c = buffer.char_at(idx);
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                end = Some(idx);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let end = end?;
+    if !inner {
+        return Some(Range::new(
+            buffer.char_to_pos(start),
+            buffer.char_to_pos(end + 1),
+        ));
+    }
+    let mut from = start + 1;
+    let mut to = end;
+    if from < to && buffer.char_at(from) == '\n' {
+        from += 1;
+        let close_line = buffer.char_to_pos(to);
+        let indent = buffer.pos_to_char(Position::new(close_line.line, 0));
+        if (indent..to).all(|idx| is_blank(buffer.char_at(idx))) && indent >= from {
+            to = indent;
+        }
+    }
+    Some(Range::new(buffer.char_to_pos(from), buffer.char_to_pos(to)))
+}
+
+/// `ip` is the run of non-blank (or blank) lines around `line`; `ap` adds
+/// the blank lines after it.
+fn paragraph(buffer: &Buffer, line: usize, inner: bool) -> Span {
+    let last_line = buffer.get_lines_count() - 1;
+    let blank = |line: usize| buffer.get_line_at(line).trim().is_empty();
+    let kind = blank(line);
+    let mut first = line;
+    while first > 0 && blank(first - 1) == kind {
+        first -= 1;
+    }
+    let mut last = line;
+    while last < last_line && blank(last + 1) == kind {
+        last += 1;
+    }
+    if !inner && !kind {
+        while last < last_line && blank(last + 1) {
+            last += 1;
+        }
+    }
+    Span::Lines(first, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the object typed as `c` after `i` (or `a` unless `inner`)
+    /// covers in `text` with the cursor at `line`, `col`.
+    fn select(text: &str, (line, col): (usize, usize), c: char, inner: bool) -> Option<Span> {
+        let buffer = Buffer::from_reader(text.as_bytes()).unwrap();
+        TextObject::from_char(c, inner)
+            .unwrap()
+            .select(&buffer, Position::new(line, col))
+    }
+
+    fn chars(line: usize, start: usize, end: usize) -> Option<Span> {
+        Some(Span::Chars(Range::new(
+            Position::new(line, start),
+            Position::new(line, end),
+        )))
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(select("foo bar  baz", (0, 5), 'w', true), chars(0, 4, 7));
+        assert_eq!(select("foo bar  baz", (0, 5), 'w', false), chars(0, 4, 9));
+        // Without blanks after the word, `aw` takes the ones before it.
+        assert_eq!(select("foo bar  baz", (0, 10), 'w', false), chars(0, 7, 12));
+        assert_eq!(select("foo bar", (0, 3), 'w', true), chars(0, 3, 4));
+        assert_eq!(select("foo.bar", (0, 1), 'w', true), chars(0, 0, 3));
+        assert_eq!(select("foo.bar", (0, 3), 'w', true), chars(0, 3, 4));
+        assert_eq!(select("foo.bar x", (0, 1), 'W', true), chars(0, 0, 7));
+        assert_eq!(select("", (0, 0), 'w', true), chars(0, 0, 0));
+    }
+
+    #[test]
+    fn quoted_strings() {
+        let text = r#"say "hi there" now"#;
+        assert_eq!(select(text, (0, 6), '"', true), chars(0, 5, 13));
+        assert_eq!(select(text, (0, 6), '"', false), chars(0, 4, 15));
+        // The first string after the cursor when it isn't in one.
+        assert_eq!(select(text, (0, 0), '"', true), chars(0, 5, 13));
+        assert_eq!(select(text, (0, 16), '"', true), None);
+        assert_eq!(select(r#""a\"b""#, (0, 1), '"', true), chars(0, 1, 5));
+        assert_eq!(select("", (0, 0), '"', true), None);
+    }
+
+    #[test]
+    fn blocks() {
+        let text = "f(a, (b), c)";
+        assert_eq!(select(text, (0, 2), '(', true), chars(0, 2, 11));
+        assert_eq!(select(text, (0, 2), ')', false), chars(0, 1, 12));
+        assert_eq!(select(text, (0, 6), 'b', true), chars(0, 6, 7));
+        // On a bracket, the pair it belongs to.
+        assert_eq!(select(text, (0, 7), '(', false), chars(0, 5, 8));
+        assert_eq!(select(text, (0, 5), '(', false), chars(0, 5, 8));
+        assert_eq!(select(text, (0, 0), '(', true), None);
+        assert_eq!(select("(a) x", (0, 4), '(', true), None);
+        assert_eq!(select("(a", (0, 1), '(', true), None);
+        assert_eq!(select("", (0, 0), '(', true), None);
+        assert_eq!(select("", (0, 0), '{', false), None);
+    }
+
+    #[test]
+    fn blocks_across_lines_leave_out_the_brackets_lines() {
+        let text = "if x {\n    a\n}\n";
+        assert_eq!(
+            select(text, (1, 4), '{', true),
+            Some(Span::Chars(Range::new(
+                Position::new(1, 0),
+                Position::new(2, 0)
+            )))
+        );
+        assert_eq!(
+            select(text, (1, 4), 'B', false),
+            Some(Span::Chars(Range::new(
+                Position::new(0, 5),
+                Position::new(2, 1)
+            )))
+        );
+    }
+
+    #[test]
+    fn paragraphs() {
+        let text = "a\nb\n\n\nc";
+        assert_eq!(select(text, (0, 0), 'p', true), Some(Span::Lines(0, 1)));
+        assert_eq!(select(text, (1, 0), 'p', false), Some(Span::Lines(0, 3)));
+        assert_eq!(select(text, (2, 0), 'p', true), Some(Span::Lines(2, 3)));
+        assert_eq!(select(text, (4, 0), 'p', false), Some(Span::Lines(4, 4)));
+        assert_eq!(select("", (0, 0), 'p', false), Some(Span::Lines(0, 0)));
+    }
+}
@@ -0,0 +1,377 @@
+extern crate ignore;
+
+use glium::glutin::{ElementState, ModifiersState, VirtualKeyCode};
+use glium::{Display, Frame};
+use glium_glyph::glyph_brush::{
+    rusttype::Font, GlyphCruncher, HorizontalAlign, Layout, Section, SectionText, VariedSection,
+};
+use glium_glyph::GlyphBrush;
+use ignore::WalkBuilder;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::constants::{BASE_FONT_SIZE, CMD_HOLD, CTRL_HOLD, NO_MODIFIERS, TAB_STOP};
+use crate::editor::grapheme;
+use crate::fuzzy::{self, Match};
+use crate::layout_manager::{Action, View};
+use crate::ui::color;
+use crate::ui::list::{self, Selection, ROW_HEIGHT, TOP};
+use crate::ui::panel::Panel;
+
+/// Width of the finder, in logical pixels.
+const WIDTH: f32 = 700.0;
+/// Space between the list and the preview, in logical pixels.
+const GAP: f32 = 8.0;
+/// Lines of the highlighted file shown under the list.
+const PREVIEW_LINES: usize = 15;
+/// How much of the highlighted file is read for the preview.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// A list of the files under the working directory, narrowed down with a
+/// fuzzy match on their paths, with a look at the one highlighted. Opens on
+/// Cmd-P.
+pub struct FinderView<'a, 'b> {
+    glyph_brush: GlyphBrush<'a, 'b>,
+    padding: f32,
+    font_size: f32,
+    /// Every path found so far, relative to the working directory.
+    paths: Vec<String>,
+    /// Where the paths come from while the directory is being walked.
+    walker: Option<Receiver<String>>,
+    query: String,
+    /// The query the results were filtered with.
+    filtered: String,
+    /// The paths matching the query, best first, by index.
+    results: Vec<(usize, Match)>,
+    /// Which result Enter opens.
+    selection: Selection,
+    /// The first lines of a path, by index.
+    preview: Option<(usize, Vec<String>)>,
+    visible: bool,
+    background: Panel,
+    highlight: Panel,
+    preview_background: Panel,
+    actions: Vec<Action>,
+}
+
+impl<'a, 'b> FinderView<'a, 'b> {
+    pub fn new(display: &Display) -> FinderView<'a, 'b> {
+        let font_regular: &[u8] = include_bytes!("../../assets/haskplex.ttf");
+        let fonts = vec![Font::from_bytes(font_regular).unwrap()];
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+
+        FinderView {
+            glyph_brush: GlyphBrush::new(display, fonts),
+            padding: 15.0,
+            font_size: BASE_FONT_SIZE * hidpi_factor,
+            paths: Vec::new(),
+            walker: None,
+            query: String::new(),
+            filtered: String::new(),
+            results: Vec::new(),
+            selection: Selection::new(),
+            preview: None,
+            visible: false,
+            background: Panel::new(
+                display,
+                [0.0, 0.0],
+                [WIDTH, ROW_HEIGHT],
+                color::hex("#12191D").as_slice(),
+            ),
+            highlight: Panel::new(
+                display,
+                [0.0, 0.0],
+                [WIDTH, ROW_HEIGHT],
+                color::hex("#263238").as_slice(),
+            ),
+            preview_background: Panel::new(
+                display,
+                [0.0, 0.0],
+                [WIDTH, ROW_HEIGHT],
+                color::hex("#0E1417").as_slice(),
+            ),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Shows the finder and starts walking the directory again, so files
+    /// made since last time show up.
+    fn open(&mut self) {
+        self.visible = true;
+        self.query.clear();
+        self.filtered.clear();
+        self.paths.clear();
+        self.results.clear();
+        self.selection.reset();
+        self.preview = None;
+        self.walker = Some(walk(Path::new(".")));
+    }
+
+    /// Takes in the paths found since last time and merges the ones that
+    /// match into the results, leaving the others as they were scored.
+    fn receive_paths(&mut self) {
+        let start = self.paths.len();
+        if let Some(walker) = &self.walker {
+            self.paths.extend(walker.try_iter());
+        }
+        if self.paths.len() == start {
+            return;
+        }
+        let paths = &self.paths;
+        let candidates = (start..paths.len()).map(|i| (i, paths[i].as_str()));
+        let found = fuzzy::filter(&self.filtered, candidates);
+        let results = std::mem::take(&mut self.results);
+        self.results = fuzzy::merge(&self.filtered, results, found, |i| paths[i].chars().count());
+        self.selection.clamp(self.results.len());
+    }
+
+    /// Matches the paths against the query again. When more was typed
+    /// onto the end, only those that matched before can still match.
+    fn refilter(&mut self) {
+        let candidates = if self.query.starts_with(&self.filtered) {
+            self.results.iter().map(|(i, _)| *i).collect()
+        } else {
+            (0..self.paths.len()).collect()
+        };
+        self.filter(candidates);
+        self.selection.reset();
+    }
+
+    /// Matches the query against the paths at `candidates`.
+    fn filter(&mut self, candidates: Vec<usize>) {
+        let paths = &self.paths;
+        let candidates = candidates.into_iter().map(|i| (i, paths[i].as_str()));
+        self.results = fuzzy::filter(&self.query, candidates);
+        self.filtered = self.query.clone();
+        self.selection.clamp(self.results.len());
+    }
+
+    fn open_selected(&mut self) {
+        if let Some((index, _)) = self.results.get(self.selection.selected) {
+            self.actions
+                .push(Action::OpenFile(self.paths[*index].clone()));
+        }
+        self.visible = false;
+        self.walker = None;
+    }
+
+    /// Reads the start of the selected path unless it's already shown.
+    fn update_preview(&mut self) {
+        let index = match self.results.get(self.selection.selected) {
+            Some((index, _)) => *index,
+            None => {
+                self.preview = None;
+                return;
+            }
+        };
+        if self.preview.as_ref().map(|(i, _)| *i) != Some(index) {
+            self.preview = Some((index, preview(&self.paths[index])));
+        }
+    }
+}
+
+/// Walks the directory at `root` on another thread, leaving out whatever
+/// `.gitignore` and `.ignore` files and hidden names leave out, and sends
+/// back the path of each file found. It stops when the receiver is dropped.
+fn walk(root: &Path) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    let root = root.to_path_buf();
+    thread::spawn(move || {
+        let walker = WalkBuilder::new(&root).require_git(false).build();
+        for entry in walker.filter_map(Result::ok) {
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let path = entry
+                .path()
+                .strip_prefix(&root)
+                .unwrap_or_else(|_| entry.path());
+            if sender.send(path.to_string_lossy().into_owned()).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// The first lines of the file at `path`, with tabs expanded to the tab
+/// stops the way the editor draws them, or a note saying why there are
+/// none.
+fn preview(path: &str) -> Vec<String> {
+    let mut bytes = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+    if let Err(err) = read {
+        return vec![format!("Can't read {}: {}", path, err)];
+    }
+    if bytes.contains(&0) {
+        return vec!["Binary file".to_owned()];
+    }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(|line| grapheme::expand_tabs(line, 0, usize::MAX, TAB_STOP, ' '))
+        .collect()
+}
+
+impl<'a, 'b> View for FinderView<'a, 'b> {
+    fn update(&mut self, display: &Display) {
+        if !self.visible {
+            return;
+        }
+        self.receive_paths();
+        self.update_preview();
+
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+        self.font_size = BASE_FONT_SIZE * hidpi_factor;
+        let screen_dims = display.get_framebuffer_dimensions();
+        let left = screen_dims.0 as f32 / hidpi_factor / 2.0 - WIDTH / 2.0;
+        let shown = self.selection.shown(self.results.len());
+        let list_height = ROW_HEIGHT * (shown + 1) as f32;
+        self.background
+            .set_rect(display, [left, TOP], [WIDTH, list_height]);
+        self.highlight.set_rect(
+            display,
+            [left, TOP + ROW_HEIGHT * self.selection.row() as f32],
+            [WIDTH, ROW_HEIGHT],
+        );
+        let preview_top = TOP + list_height + GAP;
+        self.preview_background.set_rect(
+            display,
+            [left, preview_top],
+            [WIDTH, ROW_HEIGHT * PREVIEW_LINES as f32],
+        );
+
+        let scale = glyph_brush::rusttype::Scale::uniform(self.font_size);
+        let letter_width = self
+            .glyph_brush
+            .glyph_bounds(Section {
+                text: "0",
+                scale,
+                ..Section::default()
+            })
+            .map_or(1.0, |rect| rect.width());
+        let columns = ((WIDTH - self.padding * 2.0) * hidpi_factor / letter_width) as usize;
+        let text_left = (left + self.padding) * hidpi_factor;
+        let font_size = self.font_size;
+        let row_y = |top: f32, row: usize| list::row_y(top, row, hidpi_factor, font_size);
+        let text_color = color::hex("#A3BCC4").as_slice();
+        let match_color = color::hex("#FFCB6B").as_slice();
+
+        let prompt = format!("> {}█", self.query);
+        self.glyph_brush.queue(Section {
+            text: &prompt,
+            screen_position: (text_left, row_y(TOP, 0)),
+            scale,
+            color: color::hex("#E6FFFF").as_slice(),
+            layout: Layout::default_single_line(),
+            ..Section::default()
+        });
+        let count = format!("{}/{}", self.results.len(), self.paths.len());
+        self.glyph_brush.queue(Section {
+            text: &count,
+            screen_position: ((left + WIDTH - self.padding) * hidpi_factor, row_y(TOP, 0)),
+            scale,
+            color: color::hex("#3E5863").as_slice(),
+            layout: Layout::default_single_line().h_align(HorizontalAlign::Right),
+            ..Section::default()
+        });
+
+        let results = self.results.iter().skip(self.selection.top).take(shown);
+        for (row, (index, m)) in results.enumerate() {
+            let runs = fuzzy::runs(&self.paths[*index], &m.positions);
+            self.glyph_brush.queue(VariedSection {
+                text: runs
+                    .iter()
+                    .map(|(text, matched)| SectionText {
+                        text,
+                        scale,
+                        color: if *matched { match_color } else { text_color },
+                        ..SectionText::default()
+                    })
+                    .collect(),
+                screen_position: (text_left, row_y(TOP, row + 1)),
+                layout: Layout::default_single_line(),
+                ..VariedSection::default()
+            });
+        }
+
+        if let Some((_, lines)) = &self.preview {
+            for (row, line) in lines.iter().enumerate() {
+                // Tabs are spaces by now, so any tab stop will do.
+                let fits = grapheme::col_at_display(line, columns, 1);
+                let line: String = line.chars().take(fits).collect();
+                self.glyph_brush.queue(Section {
+                    text: &line,
+                    screen_position: (text_left, row_y(preview_top, row)),
+                    scale,
+                    color: color::hex("#7C98A3").as_slice(),
+                    layout: Layout::default_single_line(),
+                    ..Section::default()
+                });
+            }
+        }
+    }
+
+    fn draw(&mut self, display: &Display, target: &mut Frame) {
+        if self.visible {
+            self.background.draw(target);
+            if !self.results.is_empty() {
+                self.highlight.draw(target);
+                self.preview_background.draw(target);
+            }
+            self.glyph_brush.draw_queued(display, target);
+        }
+    }
+
+    fn handle_input(
+        &mut self,
+        key_code: VirtualKeyCode,
+        state: ElementState,
+        modifiers: ModifiersState,
+    ) {
+        if state != ElementState::Pressed {
+            return;
+        }
+        match (key_code, modifiers) {
+            (VirtualKeyCode::P, CMD_HOLD) => self.open(),
+            _ if !self.visible => (),
+            (VirtualKeyCode::Escape, NO_MODIFIERS) => {
+                self.visible = false;
+                self.walker = None;
+            }
+            (VirtualKeyCode::Return, NO_MODIFIERS) => self.open_selected(),
+            (VirtualKeyCode::Down, NO_MODIFIERS) | (VirtualKeyCode::N, CTRL_HOLD) => {
+                self.selection.step(true, self.results.len())
+            }
+            (VirtualKeyCode::Up, NO_MODIFIERS) | (VirtualKeyCode::P, CTRL_HOLD) => {
+                self.selection.step(false, self.results.len())
+            }
+            _ => (),
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        if self.visible && !c.is_control() {
+            self.query.push(c);
+            self.refilter();
+        }
+    }
+
+    fn pop_char(&mut self) {
+        if self.visible {
+            self.query.pop();
+            self.refilter();
+        }
+    }
+
+    fn captures_input(&self) -> bool {
+        self.visible
+    }
+
+    fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+}
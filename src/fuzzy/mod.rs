@@ -0,0 +1,225 @@
+/// Points for each matched char.
+const MATCH: i64 = 16;
+/// Extra points for a char matched right after the one before it.
+const CONSECUTIVE: i64 = 16;
+/// Extra points for a char that starts a word: the first char, one after a
+/// separator like `/`, `_` or a space, or an uppercase one after a
+/// lowercase one.
+const BOUNDARY: i64 = 24;
+/// Points taken off for each char skipped between two matched ones.
+const GAP: i64 = 1;
+
+/// How well a pattern matches some text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub score: i64,
+    /// The char offsets in the text of the chars the pattern matched, in
+    /// order.
+    pub positions: Vec<usize>,
+}
+
+/// Matches `pattern` against `text` if its chars all appear in the text in
+/// the same order, though maybe with others in between, taking the best
+/// scoring way when there are several. Case is ignored unless the pattern
+/// has an uppercase letter. An empty pattern matches anything.
+pub fn score(pattern: &str, text: &str) -> Option<Match> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let text: Vec<char> = text.chars().collect();
+    if pattern.len() > text.len() {
+        return None;
+    }
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let folded: Vec<char> = text.iter().map(|&c| fold(c)).collect();
+    let bonus: Vec<i64> = (0..text.len())
+        .map(|j| match j.checked_sub(1).map(|i| text[i]) {
+            None => BOUNDARY,
+            Some(prev) if !prev.is_alphanumeric() && text[j].is_alphanumeric() => BOUNDARY,
+            Some(prev) if prev.is_lowercase() && text[j].is_uppercase() => BOUNDARY,
+            _ => 0,
+        })
+        .collect();
+
+    // best[i][j] is the best score for the first i + 1 pattern chars with
+    // the last of them at text[j], and from[i][j] where the one before is.
+    let mut best: Vec<Vec<Option<i64>>> = vec![vec![None; text.len()]; pattern.len()];
+    let mut from = vec![vec![0; text.len()]; pattern.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        // The best match of the pattern so far ending two or more chars
+        // back, with the gap up to j - 1 taken off later.
+        let mut gapped: Option<(i64, usize)> = None;
+        for j in 0..text.len() {
+            if i > 0 && j >= 2 {
+                if let Some(score) = best[i - 1][j - 2] {
+                    let score = score + (j - 2) as i64 * GAP;
+                    if gapped.is_none_or(|(best, _)| score > best) {
+                        gapped = Some((score, j - 2));
+                    }
+                }
+            }
+            if folded[j] != p {
+                continue;
+            }
+            let previous = if i == 0 {
+                Some((0, 0))
+            } else {
+                let adjacent = j
+                    .checked_sub(1)
+                    .and_then(|k| best[i - 1][k].map(|score| (score + CONSECUTIVE, k)));
+                let gapped = gapped.map(|(score, k)| (score - (j - 1) as i64 * GAP, k));
+                match (adjacent, gapped) {
+                    (Some(a), Some(g)) if g.0 > a.0 => Some(g),
+                    (Some(a), _) => Some(a),
+                    (None, g) => g,
+                }
+            };
+            if let Some((score, k)) = previous {
+                best[i][j] = Some(score + MATCH + bonus[j]);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let last = pattern.len() - 1;
+    let (mut j, score) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .max_by_key(|&(j, score)| (score, std::cmp::Reverse(j)))?;
+    let mut positions = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(Match { score, positions })
+}
+
+/// The `candidates`, texts each with an index, that match `pattern`, by
+/// index with how they match, best first. Shorter texts win ties, then
+/// lower indices. An empty pattern keeps them all in order.
+pub fn filter<'t, I>(pattern: &str, candidates: I) -> Vec<(usize, Match)>
+where
+    I: IntoIterator<Item = (usize, &'t str)>,
+{
+    let mut matches: Vec<(usize, usize, Match)> = candidates
+        .into_iter()
+        .filter_map(|(i, text)| score(pattern, text).map(|m| (i, text.chars().count(), m)))
+        .collect();
+    matches.sort_by_key(|(i, len, m)| rank(pattern, *i, *len, m));
+    matches.into_iter().map(|(i, _, m)| (i, m)).collect()
+}
+
+/// Merges `more` into `matches`, both as `filter` gave them for `pattern`,
+/// keeping its order, so new candidates can be added without scoring the
+/// others again. `len` is how many chars the text at an index has.
+pub fn merge<F>(
+    pattern: &str,
+    matches: Vec<(usize, Match)>,
+    more: Vec<(usize, Match)>,
+    len: F,
+) -> Vec<(usize, Match)>
+where
+    F: Fn(usize) -> usize,
+{
+    let rank = |(i, m): &(usize, Match)| rank(pattern, *i, len(*i), m);
+    let mut merged = Vec::with_capacity(matches.len() + more.len());
+    let mut matches = matches.into_iter().peekable();
+    let mut more = more.into_iter().peekable();
+    loop {
+        let next = match (matches.peek(), more.peek()) {
+            (Some(a), Some(b)) if rank(b) < rank(a) => more.next(),
+            (Some(_), _) => matches.next(),
+            (None, _) => more.next(),
+        };
+        match next {
+            Some(next) => merged.push(next),
+            None => return merged,
+        }
+    }
+}
+
+/// What `filter` sorts by: best score, then shortest text, then lowest
+/// index, or only the index for an empty pattern.
+fn rank(pattern: &str, i: usize, len: usize, m: &Match) -> (std::cmp::Reverse<i64>, usize, usize) {
+    if pattern.is_empty() {
+        (std::cmp::Reverse(0), 0, i)
+    } else {
+        (std::cmp::Reverse(m.score), len, i)
+    }
+}
+
+/// Splits `text` into runs of the chars at `positions` and of the others,
+/// each with whether it's at them, for drawing matches differently.
+pub fn runs(text: &str, positions: &[usize]) -> Vec<(String, bool)> {
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let matched = positions.contains(&i);
+        match runs.last_mut() {
+            Some((run, run_matched)) if *run_matched == matched => run.push(c),
+            _ => runs.push((c.to_string(), matched)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTS: &[&str] = &[
+        "src/main.rs",
+        "src/editor/mod.rs",
+        "README.md",
+        "src/editor/motion.rs",
+        "Cargo.toml",
+        "src/fuzzy/mod.rs",
+        "mod.rs",
+    ];
+
+    fn candidates(range: std::ops::Range<usize>) -> Vec<(usize, &'static str)> {
+        range.map(|i| (i, TEXTS[i])).collect()
+    }
+
+    #[test]
+    fn merging_gives_what_filtering_everything_does() {
+        for pattern in &["", "mod", "em", "src", "zzz"] {
+            let all = filter(pattern, candidates(0..TEXTS.len()));
+            for split in 0..=TEXTS.len() {
+                let first = filter(pattern, candidates(0..split));
+                let rest = filter(pattern, candidates(split..TEXTS.len()));
+                let merged = merge(pattern, first, rest, |i| TEXTS[i].chars().count());
+                assert_eq!(merged, all, "pattern {:?} split at {}", pattern, split);
+            }
+        }
+    }
+
+    #[test]
+    fn an_empty_pattern_keeps_the_order() {
+        let indices: Vec<usize> = filter("", candidates(0..TEXTS.len()))
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(indices, (0..TEXTS.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn consecutive_and_boundary_matches_win() {
+        let best = filter("mod", candidates(0..TEXTS.len()))[0].0;
+        assert_eq!(TEXTS[best], "mod.rs");
+        assert!(score("xyz", "src/main.rs").is_none());
+        assert_eq!(score("Mod", "mod.rs"), None);
+        assert_eq!(score("mr", "mod.rs").unwrap().positions, vec![0, 4]);
+    }
+}
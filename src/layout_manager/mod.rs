@@ -2,6 +2,39 @@ extern crate glium;
 use glium::glutin::{ElementState, ModifiersState, VirtualKeyCode};
 use glium::{Display, Frame};
 
+use crate::palette::Entry;
+use crate::statusline::Segment;
+
+/// Messages views send to each other through the layout manager.
+pub enum Action {
+    /// Open the command line with the given prompt.
+    OpenCmdline(char),
+    /// Run a command typed into the command line.
+    RunCommand(String),
+    /// The text in the command line changed.
+    CmdlineChanged(String),
+    /// The command line was closed without running anything.
+    CmdlineCancelled,
+    /// Ask what a command line, up to the cursor, can be completed to.
+    Complete(String),
+    /// What the command line can be completed to, each as the whole of it.
+    Completions(Vec<String>),
+    /// Show a file in the editor, opening it if needed.
+    OpenFile(String),
+    /// Ask for the commands the palette lists.
+    ListCommands,
+    /// The commands the palette lists.
+    CommandList(Vec<Entry>),
+    /// Show a message to the user.
+    ShowMessage(String),
+    /// Tell the user something went wrong.
+    ShowError(String),
+    /// Close the window.
+    Quit,
+    /// Set a segment of the status line.
+    SetStatus(Segment),
+}
+
 pub trait View {
     fn update(&mut self, display: &Display);
     fn draw(&mut self, display: &Display, target: &mut Frame);
@@ -13,10 +46,24 @@ pub trait View {
     );
     fn push_char(&mut self, c: char);
     fn pop_char(&mut self);
+
+    /// Whether this view currently takes all keyboard input.
+    fn captures_input(&self) -> bool {
+        false
+    }
+
+    /// Drains the actions this view wants to send to the others.
+    fn take_actions(&mut self) -> Vec<Action> {
+        Vec::new()
+    }
+
+    fn dispatch(&mut self, _action: &Action) {}
 }
 
 pub struct LayoutManager {
     pub views: Vec<Box<dyn View>>,
+    /// Whether a view asked to quit.
+    pub quit: bool,
 }
 
 impl LayoutManager {
@@ -24,6 +71,7 @@ impl LayoutManager {
         for view in self.views.iter_mut() {
             view.update(display);
         }
+        self.dispatch_actions();
     }
 
     pub fn draw(&mut self, display: &Display, target: &mut Frame) {
@@ -38,20 +86,56 @@ impl LayoutManager {
         state: ElementState,
         modifiers: ModifiersState,
     ) {
-        for view in self.views.iter_mut() {
+        for view in self.input_views() {
             view.handle_input(key_code, state, modifiers);
         }
+        self.dispatch_actions();
     }
 
     pub fn push_char(&mut self, c: char) {
-        for view in self.views.iter_mut() {
+        for view in self.input_views() {
             view.push_char(c);
         }
+        self.dispatch_actions();
     }
 
     pub fn pop_char(&mut self) {
-        for view in self.views.iter_mut() {
+        for view in self.input_views() {
             view.pop_char();
         }
+        self.dispatch_actions();
+    }
+
+    /// The views that should receive keyboard input: the one capturing
+    /// input if there is one, otherwise all of them.
+    fn input_views(&mut self) -> Vec<&mut Box<dyn View>> {
+        if let Some(index) = self.views.iter().position(|view| view.captures_input()) {
+            vec![&mut self.views[index]]
+        } else {
+            self.views.iter_mut().collect()
+        }
+    }
+
+    fn dispatch_actions(&mut self) {
+        let mut actions: Vec<Action> = self
+            .views
+            .iter_mut()
+            .flat_map(|view| view.take_actions())
+            .collect();
+        while !actions.is_empty() {
+            for action in actions.iter() {
+                if let Action::Quit = action {
+                    self.quit = true;
+                }
+                for view in self.views.iter_mut() {
+                    view.dispatch(action);
+                }
+            }
+            actions = self
+                .views
+                .iter_mut()
+                .flat_map(|view| view.take_actions())
+                .collect();
+        }
     }
 }
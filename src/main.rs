@@ -3,16 +3,25 @@ extern crate glium;
 extern crate glium_glyph;
 
 use glium::{glutin, Surface};
+use std::env;
+use std::process;
 
 mod cmdline;
 mod constants;
 mod editor;
+mod finder;
+mod fuzzy;
 mod layout_manager;
+mod palette;
+mod statusline;
 mod ui;
 
 use cmdline::CmdlineView;
 use editor::EditorView;
+use finder::FinderView;
 use layout_manager::LayoutManager;
+use palette::PaletteView;
+use statusline::StatusLineView;
 
 #[derive(Copy, Clone)]
 pub struct Vertex {
@@ -20,7 +29,58 @@ pub struct Vertex {
 }
 implement_vertex!(Vertex, position);
 
+const USAGE: &str = "Usage: snarkyed [+N] [file|-]...
+
+  +N     Start at line N (+ alone for the last line)
+  -      Read the buffer from stdin";
+
+/// What to open, from `snarkyed [+N] [file|-]...`.
+#[derive(Debug, PartialEq)]
+struct Args {
+    files: Vec<String>,
+    line: Option<usize>,
+    /// Whether `-h` or `--help` asked for the usage instead.
+    help: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut parsed = Args {
+        files: Vec::new(),
+        line: None,
+        help: false,
+    };
+    let mut only_files = false;
+    for arg in args {
+        if only_files {
+            parsed.files.push(arg);
+        } else if arg == "--" {
+            only_files = true;
+        } else if arg == "-h" || arg == "--help" {
+            parsed.help = true;
+        } else if let Some(line) = arg.strip_prefix('+') {
+            parsed.line = match line {
+                "" => Some(usize::MAX),
+                n => Some(n.parse().map_err(|_| format!("Invalid line: {}", arg))?),
+            };
+        } else if arg.starts_with('-') && arg != "-" {
+            return Err(format!("Unknown option: {}", arg));
+        } else {
+            parsed.files.push(arg);
+        }
+    }
+    Ok(parsed)
+}
+
 fn main() {
+    let args = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(1);
+    });
+    if args.help {
+        println!("{}", USAGE);
+        process::exit(0);
+    }
+
     let mut events_loop = glutin::EventsLoop::new();
     let wb = glutin::WindowBuilder::new()
         .with_dimensions(glium::glutin::dpi::LogicalSize::new(960.0, 600.0))
@@ -28,16 +88,25 @@ fn main() {
     let cb = glutin::ContextBuilder::new();
     let display = glium::Display::new(wb, cb, &events_loop).unwrap();
 
+    let mut editor = EditorView::new(&args.files, &display);
+    if let Some(line) = args.line {
+        editor.goto_line(line);
+    }
+
     let mut layout = LayoutManager {
         views: vec![
-            Box::new(EditorView::new("assets/source.txt", &display)),
+            Box::new(editor),
+            Box::new(StatusLineView::new(&display)),
             Box::new(CmdlineView::new(&display)),
+            Box::new(PaletteView::new(&display)),
+            Box::new(FinderView::new(&display)),
         ],
+        quit: false,
     };
 
     let mut closed = false;
 
-    while !closed {
+    while !closed && !layout.quit {
         layout.update_views(&display);
 
         let mut target = display.draw();
@@ -47,11 +116,11 @@ fn main() {
         target.finish().unwrap();
 
         events_loop.poll_events(|ev| {
-            match ev {
-                glutin::Event::WindowEvent { event, .. } => match event {
+            if let glutin::Event::WindowEvent { event, .. } = ev {
+                match event {
                     // Broadcast input event
                     glutin::WindowEvent::ReceivedCharacter(c) => {
-                        if c as u32 == 127 {
+                        if c as u32 == 127 || c as u32 == 8 {
                             layout.pop_char();
                         } else {
                             layout.push_char(c);
@@ -76,9 +145,51 @@ fn main() {
                         ..
                     } => layout.handle_input(virtual_code, state, modifiers),
                     _ => (),
-                },
-                _ => (),
+                }
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn files(args: &[&str]) -> Vec<String> {
+        parse(args).unwrap().files
+    }
+
+    #[test]
+    fn line_to_start_at() {
+        assert_eq!(parse(&["+12", "a"]).unwrap().line, Some(12));
+        assert_eq!(parse(&["a", "+3"]).unwrap().line, Some(3));
+        assert_eq!(parse(&["+"]).unwrap().line, Some(usize::MAX));
+        assert_eq!(parse(&["+1", "+2"]).unwrap().line, Some(2));
+        assert_eq!(parse(&["a"]).unwrap().line, None);
+        assert_eq!(parse(&["+x"]), Err("Invalid line: +x".to_owned()));
+        assert_eq!(parse(&["+-1"]), Err("Invalid line: +-1".to_owned()));
+    }
+
+    #[test]
+    fn files_and_stdin() {
+        assert_eq!(files(&[]), Vec::<String>::new());
+        assert_eq!(files(&["a", "-", "b"]), ["a", "-", "b"]);
+        // After `--` everything is a file, even what looks like an option.
+        assert_eq!(files(&["--", "-x", "+3", "--"]), ["-x", "+3", "--"]);
+        assert_eq!(parse(&["--", "+3"]).unwrap().line, None);
+    }
+
+    #[test]
+    fn help_and_unknown_options() {
+        assert!(parse(&["-h"]).unwrap().help);
+        assert!(parse(&["a", "--help"]).unwrap().help);
+        assert!(!parse(&["a"]).unwrap().help);
+        assert!(!parse(&["--", "-h"]).unwrap().help);
+        assert_eq!(parse(&["-x"]), Err("Unknown option: -x".to_owned()));
+        assert_eq!(parse(&["a", "--verbose"]), Err("Unknown option: --verbose".to_owned()));
+    }
+}
@@ -0,0 +1,233 @@
+use glium::glutin::{ElementState, ModifiersState, VirtualKeyCode};
+use glium::{Display, Frame};
+use glium_glyph::glyph_brush::{
+    rusttype::Font, HorizontalAlign, Layout, Section, SectionText, VariedSection,
+};
+use glium_glyph::GlyphBrush;
+
+use crate::constants::{BASE_FONT_SIZE, CMD_SHIFT_HOLD, CTRL_HOLD, NO_MODIFIERS};
+use crate::fuzzy::{self, Match};
+use crate::layout_manager::{Action, View};
+use crate::ui::color;
+use crate::ui::list::{self, Selection, ROW_HEIGHT, TOP};
+use crate::ui::panel::Panel;
+
+/// Width of the palette, in logical pixels.
+const WIDTH: f32 = 600.0;
+
+/// A command the palette can run, as `:name`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    /// The keys that run it in normal mode, if any.
+    pub keys: Option<String>,
+}
+
+/// A list of every command, narrowed down with a fuzzy match on what's
+/// typed. Opens on Cmd-Shift-P.
+pub struct PaletteView<'a, 'b> {
+    glyph_brush: GlyphBrush<'a, 'b>,
+    padding: f32,
+    font_size: f32,
+    entries: Vec<Entry>,
+    query: String,
+    /// The entries matching the query, best first, by index.
+    results: Vec<(usize, Match)>,
+    /// Which result Enter runs.
+    selection: Selection,
+    visible: bool,
+    background: Panel,
+    highlight: Panel,
+    actions: Vec<Action>,
+}
+
+impl<'a, 'b> PaletteView<'a, 'b> {
+    pub fn new(display: &Display) -> PaletteView<'a, 'b> {
+        let font_regular: &[u8] = include_bytes!("../../assets/haskplex.ttf");
+        let fonts = vec![Font::from_bytes(font_regular).unwrap()];
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+
+        PaletteView {
+            glyph_brush: GlyphBrush::new(display, fonts),
+            padding: 15.0,
+            font_size: BASE_FONT_SIZE * hidpi_factor,
+            entries: Vec::new(),
+            query: String::new(),
+            results: Vec::new(),
+            selection: Selection::new(),
+            visible: false,
+            background: Panel::new(
+                display,
+                [0.0, 0.0],
+                [WIDTH, ROW_HEIGHT],
+                color::hex("#12191D").as_slice(),
+            ),
+            highlight: Panel::new(
+                display,
+                [0.0, 0.0],
+                [WIDTH, ROW_HEIGHT],
+                color::hex("#263238").as_slice(),
+            ),
+            actions: Vec::new(),
+        }
+    }
+
+    fn open(&mut self) {
+        self.visible = true;
+        self.query.clear();
+        self.actions.push(Action::ListCommands);
+    }
+
+    /// Matches the entries against the query again and selects the best.
+    fn refilter(&mut self) {
+        let names = self
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .enumerate();
+        self.results = fuzzy::filter(&self.query, names);
+        self.selection.reset();
+    }
+
+    fn run_selected(&mut self) {
+        if let Some((index, _)) = self.results.get(self.selection.selected) {
+            let name = self.entries[*index].name.clone();
+            self.actions.push(Action::RunCommand(name));
+        }
+        self.visible = false;
+    }
+}
+
+impl<'a, 'b> View for PaletteView<'a, 'b> {
+    fn update(&mut self, display: &Display) {
+        if !self.visible {
+            return;
+        }
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+        self.font_size = BASE_FONT_SIZE * hidpi_factor;
+        let screen_dims = display.get_framebuffer_dimensions();
+        let left = screen_dims.0 as f32 / hidpi_factor / 2.0 - WIDTH / 2.0;
+        let shown = self.selection.shown(self.results.len());
+        self.background.set_rect(
+            display,
+            [left, TOP],
+            [WIDTH, ROW_HEIGHT * (shown + 1) as f32],
+        );
+        self.highlight.set_rect(
+            display,
+            [left, TOP + ROW_HEIGHT * self.selection.row() as f32],
+            [WIDTH, ROW_HEIGHT],
+        );
+
+        let scale = glyph_brush::rusttype::Scale::uniform(self.font_size);
+        let text_left = (left + self.padding) * hidpi_factor;
+        let text_right = (left + WIDTH - self.padding) * hidpi_factor;
+        let font_size = self.font_size;
+        let row_y = |row: usize| list::row_y(TOP, row, hidpi_factor, font_size);
+        let text_color = color::hex("#A3BCC4").as_slice();
+        let match_color = color::hex("#FFCB6B").as_slice();
+
+        let prompt = format!("> {}█", self.query);
+        self.glyph_brush.queue(Section {
+            text: &prompt,
+            screen_position: (text_left, row_y(0)),
+            scale,
+            color: color::hex("#E6FFFF").as_slice(),
+            layout: Layout::default_single_line(),
+            ..Section::default()
+        });
+
+        let results = self.results.iter().skip(self.selection.top).take(shown);
+        for (row, (index, m)) in results.enumerate() {
+            let entry = &self.entries[*index];
+            let runs = fuzzy::runs(&entry.name, &m.positions);
+            self.glyph_brush.queue(VariedSection {
+                text: runs
+                    .iter()
+                    .map(|(text, matched)| SectionText {
+                        text,
+                        scale,
+                        color: if *matched { match_color } else { text_color },
+                        ..SectionText::default()
+                    })
+                    .collect(),
+                screen_position: (text_left, row_y(row + 1)),
+                layout: Layout::default_single_line(),
+                ..VariedSection::default()
+            });
+            if let Some(keys) = &entry.keys {
+                self.glyph_brush.queue(Section {
+                    text: keys,
+                    screen_position: (text_right, row_y(row + 1)),
+                    scale,
+                    color: color::hex("#3E5863").as_slice(),
+                    layout: Layout::default_single_line().h_align(HorizontalAlign::Right),
+                    ..Section::default()
+                });
+            }
+        }
+    }
+
+    fn draw(&mut self, display: &Display, target: &mut Frame) {
+        if self.visible {
+            self.background.draw(target);
+            if !self.results.is_empty() {
+                self.highlight.draw(target);
+            }
+            self.glyph_brush.draw_queued(display, target);
+        }
+    }
+
+    fn handle_input(
+        &mut self,
+        key_code: VirtualKeyCode,
+        state: ElementState,
+        modifiers: ModifiersState,
+    ) {
+        if state != ElementState::Pressed {
+            return;
+        }
+        match (key_code, modifiers) {
+            (VirtualKeyCode::P, CMD_SHIFT_HOLD) => self.open(),
+            _ if !self.visible => (),
+            (VirtualKeyCode::Escape, NO_MODIFIERS) => self.visible = false,
+            (VirtualKeyCode::Return, NO_MODIFIERS) => self.run_selected(),
+            (VirtualKeyCode::Down, NO_MODIFIERS) | (VirtualKeyCode::N, CTRL_HOLD) => {
+                self.selection.step(true, self.results.len())
+            }
+            (VirtualKeyCode::Up, NO_MODIFIERS) | (VirtualKeyCode::P, CTRL_HOLD) => {
+                self.selection.step(false, self.results.len())
+            }
+            _ => (),
+        }
+    }
+
+    fn push_char(&mut self, c: char) {
+        if self.visible && !c.is_control() {
+            self.query.push(c);
+            self.refilter();
+        }
+    }
+
+    fn pop_char(&mut self) {
+        if self.visible {
+            self.query.pop();
+            self.refilter();
+        }
+    }
+
+    fn captures_input(&self) -> bool {
+        self.visible
+    }
+
+    fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    fn dispatch(&mut self, action: &Action) {
+        if let Action::CommandList(entries) = action {
+            self.entries = entries.clone();
+            self.refilter();
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use glium::glutin::{ElementState, ModifiersState, VirtualKeyCode};
+use glium::{Display, Frame};
+use glium_glyph::glyph_brush::{rusttype::Font, HorizontalAlign, Layout, Section};
+use glium_glyph::GlyphBrush;
+
+use crate::constants::{BASE_FONT_SIZE, STATUS_LINE_HEIGHT};
+use crate::layout_manager::{Action, View};
+use crate::ui::color;
+use crate::ui::panel::Panel;
+
+/// Which end of the status line a segment goes at.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A piece of the status line. Any view can set one with
+/// `Action::SetStatus`, and setting one with the same name again replaces
+/// it. Segments keep the order they were first set in, and empty ones are
+/// left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub name: &'static str,
+    pub text: String,
+    pub side: Side,
+}
+
+impl Segment {
+    pub fn new(name: &'static str, text: String, side: Side) -> Segment {
+        Segment { name, text, side }
+    }
+}
+
+/// The bar along the bottom of the window.
+pub struct StatusLineView<'a, 'b> {
+    glyph_brush: GlyphBrush<'a, 'b>,
+    padding: f32,
+    font_size: f32,
+    segments: Vec<Segment>,
+    background: Panel,
+}
+
+impl<'a, 'b> StatusLineView<'a, 'b> {
+    pub fn new(display: &Display) -> StatusLineView<'a, 'b> {
+        let font_regular: &[u8] = include_bytes!("../../assets/haskplex.ttf");
+        let fonts = vec![Font::from_bytes(font_regular).unwrap()];
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+        let (position, size) = StatusLineView::rect(display);
+
+        StatusLineView {
+            glyph_brush: GlyphBrush::new(display, fonts),
+            padding: 15.0,
+            font_size: BASE_FONT_SIZE * hidpi_factor,
+            segments: Vec::new(),
+            background: Panel::new(display, position, size, color::hex("#12191D").as_slice()),
+        }
+    }
+
+    /// Where the bar goes, in logical pixels: across the bottom of the
+    /// window.
+    fn rect(display: &Display) -> ([f32; 2], [f32; 2]) {
+        let screen_dims = display.get_framebuffer_dimensions();
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+        let width = screen_dims.0 as f32 / hidpi_factor;
+        let height = screen_dims.1 as f32 / hidpi_factor;
+        (
+            [0.0, height - STATUS_LINE_HEIGHT],
+            [width, STATUS_LINE_HEIGHT],
+        )
+    }
+
+    fn set_segment(&mut self, segment: &Segment) {
+        match self.segments.iter_mut().find(|s| s.name == segment.name) {
+            Some(existing) => *existing = segment.clone(),
+            None => self.segments.push(segment.clone()),
+        }
+    }
+
+    /// The text of the segments on `side`, spaced apart.
+    fn text(&self, side: Side) -> String {
+        self.segments
+            .iter()
+            .filter(|segment| segment.side == side && !segment.text.is_empty())
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
+impl<'a, 'b> View for StatusLineView<'a, 'b> {
+    fn update(&mut self, display: &Display) {
+        let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
+        self.font_size = BASE_FONT_SIZE * hidpi_factor;
+        let (position, size) = StatusLineView::rect(display);
+        self.background.set_rect(display, position, size);
+
+        let screen_dims = display.get_framebuffer_dimensions();
+        let height = STATUS_LINE_HEIGHT * hidpi_factor;
+        let text_y = screen_dims.1 as f32 - height + (height - self.font_size) / 2.0;
+        let padding = self.padding * hidpi_factor;
+        let text_color = color::hex("#A3BCC4");
+
+        let left = self.text(Side::Left);
+        self.glyph_brush.queue(Section {
+            text: &left,
+            screen_position: (padding, text_y),
+            scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+            color: text_color.as_slice(),
+            layout: Layout::default_single_line(),
+            ..Section::default()
+        });
+
+        let right = self.text(Side::Right);
+        self.glyph_brush.queue(Section {
+            text: &right,
+            screen_position: (screen_dims.0 as f32 - padding, text_y),
+            scale: glyph_brush::rusttype::Scale::uniform(self.font_size),
+            color: text_color.as_slice(),
+            layout: Layout::default_single_line().h_align(HorizontalAlign::Right),
+            ..Section::default()
+        });
+    }
+
+    fn draw(&mut self, display: &Display, target: &mut Frame) {
+        self.background.draw(target);
+        self.glyph_brush.draw_queued(display, target);
+    }
+
+    fn handle_input(
+        &mut self,
+        _key_code: VirtualKeyCode,
+        _state: ElementState,
+        _modifiers: ModifiersState,
+    ) {
+    }
+
+    fn push_char(&mut self, _c: char) {}
+
+    fn pop_char(&mut self) {}
+
+    fn dispatch(&mut self, action: &Action) {
+        if let Action::SetStatus(segment) = action {
+            self.set_segment(segment);
+        }
+    }
+}
@@ -22,7 +22,7 @@ pub fn rgba(r: u8, g: u8, b: u8, a: f32) -> Color {
     r: r as f32 / 255.0,
     g: g as f32 / 255.0,
     b: b as f32 / 255.0,
-    a: a
+    a
   }
 }
 
@@ -43,9 +43,9 @@ pub fn hexa(h: &str, a: f32) -> Color {
 fn parse_hex(hex_asm: &str) -> Vec<u8> {
     let mut hex_bytes = hex_asm.as_bytes().iter().filter_map(|b| {
         match b {
-            b'0'...b'9' => Some(b - b'0'),
-            b'a'...b'f' => Some(b - b'a' + 10),
-            b'A'...b'F' => Some(b - b'A' + 10),
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            b'A'..=b'F' => Some(b - b'A' + 10),
             _ => None,
         }
     }).fuse();
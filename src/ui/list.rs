@@ -0,0 +1,92 @@
+/// Height of each row of a list, in logical pixels.
+pub const ROW_HEIGHT: f32 = 28.0;
+/// Space between a list and the top of the window, in logical pixels.
+pub const TOP: f32 = 60.0;
+/// Most results a list shows at once.
+pub const MAX_RESULTS: usize = 10;
+
+/// Which result of a list of them is selected and which one is shown
+/// first, for the ones that pop up under a prompt like the palette's and
+/// the finder's.
+pub struct Selection {
+    pub selected: usize,
+    pub top: usize,
+}
+
+impl Selection {
+    pub fn new() -> Selection {
+        Selection {
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    /// Selects the first result.
+    pub fn reset(&mut self) {
+        self.selected = 0;
+        self.top = 0;
+    }
+
+    /// Moves the selection `down` or up one of `len` results, wrapping
+    /// around the ends and scrolling to keep it shown.
+    pub fn step(&mut self, down: bool, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.selected = if down {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + MAX_RESULTS {
+            self.top = self.selected + 1 - MAX_RESULTS;
+        }
+    }
+
+    /// Keeps the selection within `len` results.
+    pub fn clamp(&mut self, len: usize) {
+        self.selected = self.selected.min(len.saturating_sub(1));
+    }
+
+    /// How many of `len` results are shown.
+    pub fn shown(&self, len: usize) -> usize {
+        len.saturating_sub(self.top).min(MAX_RESULTS)
+    }
+
+    /// The row the selection is drawn on, under the prompt on row 0.
+    pub fn row(&self) -> usize {
+        self.selected.saturating_sub(self.top) + 1
+    }
+}
+
+/// Where text of `font_size` physical pixels goes down the screen to sit
+/// in the middle of row `row` of a list starting `top` logical pixels
+/// down.
+pub fn row_y(top: f32, row: usize, hidpi_factor: f32, font_size: f32) -> f32 {
+    (top + ROW_HEIGHT * row as f32) * hidpi_factor + (ROW_HEIGHT * hidpi_factor - font_size) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stepping_wraps_and_scrolls() {
+        let mut selection = Selection::new();
+        let len = MAX_RESULTS + 5;
+        selection.step(false, len);
+        assert_eq!((selection.selected, selection.top), (len - 1, 5));
+        assert_eq!(selection.row(), MAX_RESULTS);
+        selection.step(true, len);
+        assert_eq!((selection.selected, selection.top), (0, 0));
+        for _ in 0..MAX_RESULTS {
+            selection.step(true, len);
+        }
+        assert_eq!((selection.selected, selection.top), (MAX_RESULTS, 1));
+        assert_eq!(selection.shown(len), MAX_RESULTS);
+        selection.step(true, 0);
+        assert_eq!(selection.selected, MAX_RESULTS);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod panel;
-pub mod color;
\ No newline at end of file
+pub mod color;
+pub mod list;
\ No newline at end of file
@@ -12,6 +12,54 @@ pub struct Panel {
 
 impl Panel {
     pub fn new(display: &Display, position: [f32; 2], size: [f32; 2], color: [f32; 4]) -> Panel {
+        let vb = Panel::vertices(display, position, size);
+        let indices: [u16; 6] = [0, 1, 2, 2, 0, 3];
+        let ib = glium::IndexBuffer::new(
+            display,
+            glium::index::PrimitiveType::TrianglesList,
+            &indices,
+        )
+        .unwrap();
+        let vertex_shader_src = r#"
+            #version 140
+            in vec2 position;
+            void main() {
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "#;
+
+        let fragment_shader_src = r#"
+            #version 140
+            uniform vec4 fill;
+            out vec4 color;
+            void main() {
+                color = fill;
+            }
+        "#;
+        let pg = glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None)
+            .unwrap();
+        Panel {
+            position,
+            size,
+            color,
+            vb,
+            ib,
+            pg,
+        }
+    }
+
+    /// Moves and resizes the panel, e.g. to follow the window.
+    pub fn set_rect(&mut self, display: &Display, position: [f32; 2], size: [f32; 2]) {
+        if position != self.position || size != self.size {
+            self.vb = Panel::vertices(display, position, size);
+            self.position = position;
+            self.size = size;
+        }
+    }
+
+    /// The corners of a panel at `position` of `size`, in logical pixels
+    /// from the top left of the window.
+    fn vertices(display: &Display, position: [f32; 2], size: [f32; 2]) -> glium::VertexBuffer<Vertex> {
         let (screen_width, screen_height) = display.get_framebuffer_dimensions();
         let hidpi_factor = display.gl_window().window().get_hidpi_factor() as f32;
         let (origin_width, origin_height) = (
@@ -45,41 +93,7 @@ impl Panel {
             ],
         };
         let shape = vec![v_top_left, v_top_right, v_bottom_right, v_bottom_left];
-        let indices: [u16; 6] = [0, 1, 2, 2, 0, 3];
-        let vb = glium::VertexBuffer::new(display, &shape).unwrap();
-        let ib = glium::IndexBuffer::new(
-            display,
-            glium::index::PrimitiveType::TrianglesList,
-            &indices,
-        )
-        .unwrap();
-        let vertex_shader_src = r#"
-            #version 140
-            in vec2 position;
-            void main() {
-                gl_Position = vec4(position, 0.0, 1.0);
-            }
-        "#;
-
-        let fragment_shader_src = &format!(r#"
-            #version 140
-            out vec4 color;
-            void main() {{
-                color = vec4({}, {}, {}, {});
-            }}
-        "#,
-        color[0], color[1], color[2], color[3]
-        );
-        let pg = glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None)
-            .unwrap();
-        Panel {
-            position: position,
-            size: size,
-            color: color,
-            vb: vb,
-            ib: ib,
-            pg: pg,
-        }
+        glium::VertexBuffer::new(display, &shape).unwrap()
     }
 
     pub fn draw(&mut self, target: &mut Frame) {
@@ -88,7 +102,7 @@ impl Panel {
                 &self.vb,
                 &self.ib,
                 &self.pg,
-                &glium::uniforms::EmptyUniforms,
+                &uniform! { fill: self.color },
                 &Default::default(),
             )
             .unwrap();